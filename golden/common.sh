@@ -4,3 +4,7 @@ if ! command -v qsym 1>/dev/null; then
 	echo "Error: Couldn't find qsym in \$PATH'" 1>&2
 	exit 1
 fi
+
+# Test cases resume from a copy of their checkpoint, see run.sh.
+CHECKPOINT="$(mktemp)"
+trap 'rm -f "${CHECKPOINT}"' EXIT
@@ -18,6 +18,18 @@ for test in *; do
 		exit 1
 	)
 
-	qsym "${test}"/input.qbe "${ENTRY_FUNC}" \
+	# Test cases may pass additional options to qsym(1).
+	flags=
+	if [ -r "${test}"/flags ]; then
+		flags="$(cat "${test}"/flags)"
+	fi
+
+	# Resuming overwrites the checkpoint, hence a copy of it is used.
+	if [ -r "${test}"/checkpoint ]; then
+		cp "${test}"/checkpoint "${CHECKPOINT}"
+		flags="${flags} --checkpoint ${CHECKPOINT} --resume"
+	fi
+
+	qsym ${flags} "${test}"/input.qbe "${ENTRY_FUNC}" \
 		1>"${test}"/expected 2>&1
 done
@@ -10,7 +10,19 @@ for test in *; do
 	name="${test##*/}"
 	printf "Running test case '%s': " "${name}"
 
-	qsym "${test}"/input.qbe "${ENTRY_FUNC}" 2>&1 | \
+	# Test cases may pass additional options to qsym(1).
+	flags=
+	if [ -r "${test}"/flags ]; then
+		flags="$(cat "${test}"/flags)"
+	fi
+
+	# Resuming overwrites the checkpoint, hence a copy of it is used.
+	if [ -r "${test}"/checkpoint ]; then
+		cp "${test}"/checkpoint "${CHECKPOINT}"
+		flags="${flags} --checkpoint ${CHECKPOINT} --resume"
+	fi
+
+	qsym ${flags} "${test}"/input.qbe "${ENTRY_FUNC}" 2>&1 | \
 		cmp - "${test}/expected" 2>/dev/null 1>&2
 	if [ $? -ne 0 ]; then
 		echo FAIL
@@ -0,0 +1,81 @@
+use std::fmt::Write;
+use std::fs;
+use std::io;
+
+// Progress of an exploration which is periodically written to a file,
+// allowing the exploration to be resumed later. Pending paths are stored
+// as branch decision prefixes, their state is re-derived by replaying
+// these decisions (see Interp::exec_symbolic).
+#[derive(Default)]
+pub struct Checkpoint {
+    // Pending paths, the last prefix is explored next.
+    pub pending: Vec<Vec<bool>>,
+    // Covered blocks as pairs of function name and block label.
+    pub coverage: Vec<(String, String)>,
+    // Amount of paths which have been fully explored.
+    pub paths: u64,
+}
+
+pub fn format_decisions(decisions: &[bool]) -> String {
+    decisions
+        .iter()
+        .map(|d| if *d { '1' } else { '0' })
+        .collect()
+}
+
+pub fn parse_decisions(s: &str) -> Option<Vec<bool>> {
+    s.chars()
+        .map(|c| match c {
+            '1' => Some(true),
+            '0' => Some(false),
+            _ => None,
+        })
+        .collect()
+}
+
+fn invalid(line: &str) -> io::Error {
+    let msg = format!("invalid checkpoint line '{}'", line);
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Checkpoint {
+    pub fn write(&self, file: &str) -> io::Result<()> {
+        let mut out = String::new();
+        writeln!(out, "paths {}", self.paths).unwrap();
+        for prefix in self.pending.iter() {
+            writeln!(out, "pending {}", format_decisions(prefix)).unwrap();
+        }
+        for (func, label) in self.coverage.iter() {
+            writeln!(out, "covered {} {}", func, label).unwrap();
+        }
+
+        // Write to a temporary file first to ensure that an existing
+        // checkpoint isn't corrupted if qsym is killed while writing.
+        let tmp = format!("{}.tmp", file);
+        fs::write(&tmp, out)?;
+        fs::rename(&tmp, file)
+    }
+
+    pub fn read(file: &str) -> io::Result<Checkpoint> {
+        let mut checkpoint = Checkpoint::default();
+        for line in fs::read_to_string(file)?.lines() {
+            let (key, value) = line.split_once(' ').ok_or_else(|| invalid(line))?;
+            match key {
+                "paths" => checkpoint.paths = value.parse().map_err(|_| invalid(line))?,
+                "pending" => {
+                    let prefix = parse_decisions(value).ok_or_else(|| invalid(line))?;
+                    checkpoint.pending.push(prefix);
+                }
+                "covered" => {
+                    let (func, label) = value.split_once(' ').ok_or_else(|| invalid(line))?;
+                    checkpoint
+                        .coverage
+                        .push((func.to_string(), label.to_string()));
+                }
+                _ => return Err(invalid(line)),
+            }
+        }
+
+        Ok(checkpoint)
+    }
+}
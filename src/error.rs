@@ -1,14 +1,18 @@
 #[derive(Debug)]
 pub enum Error {
     HaltExecution,
+    PathTruncated(String),
+    SolverUnknown,
+    Fault(String),
     UnknownLabel(String),
     UnknownFunction(String),
     UnknownVariable(String),
+    UnknownType(String),
     InvalidSubtyping,
-    ForkFailed,
-    WaitpidFailed,
     UnsupportedStringType,
     InvalidCall,
     MissingJump,
     PhiAtFuncStart,
+    InvalidFloatInstr,
+    CheckpointFailed(std::io::Error),
 }
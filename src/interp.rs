@@ -1,88 +1,397 @@
-use libc::{c_int, fork, waitpid};
 use qbe_reader::types::*;
 use qbe_reader::Definition;
-use std::process::exit;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 use z3::{
     ast::{Ast, Bool, BV},
-    Context,
+    Context, Params, SatResult,
 };
 
+use crate::checkpoint::*;
 use crate::error::*;
+use crate::libc;
+use crate::memory::ObjectKind;
+use crate::search::*;
 use crate::state::*;
 use crate::value::*;
 
+// Bounds for the exploration of a single path. Paths exceeding
+// any of these bounds are not explored further (truncated).
+#[derive(Clone, Copy, Default)]
+pub struct Limits {
+    pub max_visits: Option<u32>,
+    pub max_branches: Option<u32>,
+    pub max_insts: Option<u64>,
+}
+
+// Determines how `unknown` results of the SMT solver are treated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnknownPolicy {
+    Feasible,
+    Infeasible,
+    Abort,
+}
+
+impl FromStr for UnknownPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "feasible" => Ok(UnknownPolicy::Feasible),
+            "infeasible" => Ok(UnknownPolicy::Infeasible),
+            "abort" => Ok(UnknownPolicy::Abort),
+            _ => Err(()),
+        }
+    }
+}
+
+// Determines how symbolic sizes of heap allocations are treated.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SizePolicy {
+    // Keep the size symbolic, the size of the object is symbolic too.
+    #[default]
+    Symbolic,
+    // Constrain the size to a single value satisfying the path constraints.
+    Constrain,
+    // Fork on the size: one path is constrained to a single value,
+    // the other path excludes this value and forks again on the size.
+    Fork,
+}
+
+impl FromStr for SizePolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "symbolic" => Ok(SizePolicy::Symbolic),
+            "constrain" => Ok(SizePolicy::Constrain),
+            "fork" => Ok(SizePolicy::Fork),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct SolverOptions {
+    // Timeout for a single solver query in milliseconds.
+    pub timeout: Option<u32>,
+    pub unknown: UnknownPolicy,
+}
+
+impl Default for SolverOptions {
+    fn default() -> Self {
+        SolverOptions {
+            timeout: None,
+            unknown: UnknownPolicy::Abort,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Options {
+    pub limits: Limits,
+    pub solver: SolverOptions,
+
+    // Merge paths at control-flow join points.
+    pub merge: bool,
+
+    // Treatment of symbolic sizes passed to malloc and friends.
+    pub alloc_size: SizePolicy,
+
+    // Report the branch decisions of each path, see Interp::exec_replay.
+    pub print_decisions: bool,
+}
+
+// Execution statistics of a single path, checked against the Limits.
+#[derive(Clone, Default)]
+struct PathStats<'src> {
+    visits: HashMap<BlockId<'src>, u32>,
+    branches: u32,
+    insts: u64,
+}
+
 pub struct Interp<'ctx, 'src> {
+    ctx: &'ctx Context,
     v: ValueFactory<'ctx>,
     state: State<'ctx, 'src>,
     solver: z3::Solver<'ctx>,
+    opts: Options,
+
+    // Constraints asserted on the solver for the current path,
+    // each constraint is asserted in a separate solver scope.
+    constraints: Vec<Bool<'ctx>>,
+    stats: PathStats<'src>,
+
+    // Branch decisions taken on the current path and decisions which
+    // still need to be replayed. A decision is true if the non-zero
+    // target of a conditional jump has been taken or if the function
+    // pointer of an indirect call points to the considered function.
+    trace: Vec<bool>,
+    replay: VecDeque<bool>,
+    report_replay: bool,
+
+    // Symbolic parameters of the entry function and, in concolic
+    // mode, the concrete values which are assigned to them.
+    inputs: Vec<BV<'ctx>>,
+    concrete: Option<Vec<u64>>,
 
-    // Primitive control-flow tracking for PHI instructions.
-    prev_label: Option<&'src str>,
+    // Output of the current path, written to stdout when the path ends.
+    // When forking, pending output is passed on to the first target.
+    out: String,
+
+    // File to which checkpoints are written and the interval between them.
+    checkpoint: Option<(String, Duration)>,
+    last_checkpoint: Instant,
+
+    // Amount of paths which have been fully explored.
+    paths: u64,
+
+    // Blocks with multiple predecessors where paths are suspended
+    // for state merging, None if state merging is disabled. The
+    // suspension is skipped once after resuming a merged path.
+    join_points: Option<HashSet<BlockId<'src>>>,
+    resumed_merged: bool,
+
+    // Blocks executed on any path explored so far.
+    coverage: Coverage<'src>,
 }
 
-struct Path<'ctx, 'src>(Option<Bool<'ctx>>, &'src Block);
+// Jump target, optionally guarded by a branch condition. Targets
+// without a block continue execution at the current statement.
+struct Target<'ctx, 'src>(Option<Bool<'ctx>>, Option<&'src Block>);
+
+// A pending execution path, i.e. a snapshot of the interpreter state at
+// a conditional jump (or indirect call) which has not been explored yet.
+// Paths suspended at a join point for state merging have no target.
+pub struct Path<'ctx, 'src> {
+    state: State<'ctx, 'src>,
+    constraints: Vec<Bool<'ctx>>,
+    stats: PathStats<'src>,
+    trace: Vec<bool>,
+    out: String,
+    target: Option<Target<'ctx, 'src>>,
+}
 
 enum FuncReturn<'ctx, 'src> {
-    Jump(Path<'ctx, 'src>),
-    CondJump(Path<'ctx, 'src>, Path<'ctx, 'src>),
+    Jump(Target<'ctx, 'src>),
+    CondJump(Target<'ctx, 'src>, Target<'ctx, 'src>),
     Return(Option<BV<'ctx>>),
 }
 
 enum BlockReturn<'ctx, 'src> {
-    Value(Option<BV<'ctx>>),
+    Jump(FuncReturn<'ctx, 'src>),
     Fallthrough(&'src str), // Block label to fall through from
+    Call,                   // Stack frame for callee has been pushed
+    Fork(Target<'ctx, 'src>, Target<'ctx, 'src>), // Fork within block
+}
+
+// Target of a call through a function pointer.
+enum Callee<'ctx, 'src> {
+    Func(&'src FuncDef),
+    Fork(Target<'ctx, 'src>, Target<'ctx, 'src>),
 }
 
 impl<'ctx, 'src> Path<'ctx, 'src> {
-    pub fn feasible(&self, solver: &z3::Solver<'ctx>) -> bool {
-        let cond = match &self.0 {
-            Some(x) => x,
-            None => return true,
+    // Function name and label of the block this path continues with.
+    pub fn block_id(&self) -> BlockId<'src> {
+        let func = self.state.get_cur_func();
+        let block = match &self.target {
+            Some(Target(_, Some(block))) => *block,
+            _ => self.state.get_cur_block(),
         };
+        (func.name.as_str(), block.label.as_str())
+    }
+
+    // Merge another path, suspended at the same join point, into this one.
+    fn merge(&mut self, ctx: &'ctx Context, other: Path<'ctx, 'src>) {
+        assert!(self.target.is_none() && other.target.is_none());
 
-        let r = solver.check_assumptions(&[cond.clone()]);
-        match r {
-            z3::SatResult::Unsat => false,
-            z3::SatResult::Sat => true,
-            z3::SatResult::Unknown => panic!("unknown SAT result"),
+        let common = self
+            .constraints
+            .iter()
+            .zip(other.constraints.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let suffix: Vec<_> = self.constraints[common..].iter().collect();
+        let guard = Bool::and(ctx, &suffix);
+        let other_suffix: Vec<_> = other.constraints[common..].iter().collect();
+        let other_guard = Bool::and(ctx, &other_suffix);
+
+        self.state.merge(&other.state, &guard);
+        self.out.push_str(&other.out);
+        self.constraints.truncate(common);
+        self.constraints
+            .push(Bool::or(ctx, &[&guard, &other_guard]));
+
+        for (id, n) in other.stats.visits {
+            let visits = self.stats.visits.entry(id).or_insert(0);
+            *visits = (*visits).max(n);
         }
+        self.stats.branches = self.stats.branches.max(other.stats.branches);
+        self.stats.insts = self.stats.insts.max(other.stats.insts);
+    }
+
+    // Branch decisions leading to this path, see Interp::exec_symbolic.
+    pub fn trace(&self) -> &[bool] {
+        &self.trace
+    }
+}
+
+impl<'ctx, 'src> Target<'ctx, 'src> {
+    pub fn feasible(
+        &self,
+        solver: &z3::Solver<'ctx>,
+        policy: UnknownPolicy,
+    ) -> Result<bool, Error> {
+        match &self.0 {
+            Some(cond) => feasible(solver, cond, policy),
+            None => Ok(true),
+        }
+    }
+}
+
+// Whether the condition is satisfiable under the constraints of the solver.
+fn feasible<'ctx>(
+    solver: &z3::Solver<'ctx>,
+    cond: &Bool<'ctx>,
+    policy: UnknownPolicy,
+) -> Result<bool, Error> {
+    let r = solver.check_assumptions(&[cond.clone()]);
+    match r {
+        SatResult::Unsat => Ok(false),
+        SatResult::Sat => Ok(true),
+        SatResult::Unknown => match policy {
+            UnknownPolicy::Feasible => Ok(true),
+            UnknownPolicy::Infeasible => Ok(false),
+            UnknownPolicy::Abort => Err(Error::SolverUnknown),
+        },
     }
 }
 
 impl<'ctx, 'src> Interp<'ctx, 'src> {
     pub fn new(
         ctx: &'ctx Context,
-        source: &'src Vec<Definition>,
+        source: &'src [Definition],
+        opts: Options,
     ) -> Result<Interp<'ctx, 'src>, Error> {
         let state = State::new(&ctx, source)?;
-        Ok(Interp {
+        let join_points = if opts.merge {
+            Some(Self::join_points(source))
+        } else {
+            None
+        };
+
+        let interp = Interp {
+            ctx,
             v: ValueFactory::new(ctx),
             state: state,
             solver: z3::Solver::new(&ctx),
-            prev_label: None,
-        })
+            opts,
+            constraints: Vec::new(),
+            stats: PathStats::default(),
+            trace: Vec::new(),
+            replay: VecDeque::new(),
+            report_replay: false,
+            inputs: Vec::new(),
+            concrete: None,
+            out: String::new(),
+            checkpoint: None,
+            last_checkpoint: Instant::now(),
+            paths: 0,
+            join_points,
+            resumed_merged: false,
+            coverage: Coverage::new(),
+        };
+
+        interp.set_solver_params(&interp.solver);
+        Ok(interp)
     }
 
-    fn symbolic_type(&self, name: String, ty: &Type) -> BV<'ctx> {
+    // Determine all blocks which have more than one predecessor.
+    fn join_points(source: &'src [Definition]) -> HashSet<BlockId<'src>> {
+        let mut joins = HashSet::new();
+        for x in source.iter() {
+            let func = match x {
+                Definition::Func(f) => f,
+                _ => continue,
+            };
+
+            let mut preds: HashMap<&str, usize> = HashMap::new();
+            for (i, blk) in func.body.iter().enumerate() {
+                let succs = match &blk.jump {
+                    Some(JumpInstr::Jump(l)) => vec![l.as_str()],
+                    Some(JumpInstr::Jnz(_, l1, l2)) => vec![l1.as_str(), l2.as_str()],
+                    Some(_) => vec![],
+                    None => match func.body.get(i + 1) {
+                        Some(next) => vec![next.label.as_str()],
+                        None => vec![],
+                    },
+                };
+                for succ in succs {
+                    *preds.entry(succ).or_insert(0) += 1;
+                }
+            }
+
+            for blk in func.body.iter() {
+                if preds.get(blk.label.as_str()).copied().unwrap_or(0) > 1 {
+                    joins.insert((func.name.as_str(), blk.label.as_str()));
+                }
+            }
+        }
+
+        joins
+    }
+
+    fn set_solver_params(&self, solver: &z3::Solver<'ctx>) {
+        if let Some(ms) = self.opts.solver.timeout {
+            let mut params = Params::new(self.ctx);
+            params.set_u32("timeout", ms);
+            solver.set_params(&params);
+        }
+    }
+
+    fn symbolic_type(&mut self, name: String, ty: &Type) -> Result<BV<'ctx>, Error> {
         match ty {
-            Type::Base(ty) => self.v.from_base(*ty, name),
-            Type::SubWordType(ty) => self.v.from_subw(*ty, name),
-            _ => panic!("not implemented"),
+            Type::Base(ty) => Ok(self.v.from_base(*ty, name)),
+            Type::SubWordType(ty) => Ok(self.v.from_subw(*ty, name)),
+            // Aggregates are passed as a pointer to fresh symbolic memory.
+            Type::UserDef(ty) => {
+                let (size, align) = self.state.type_layout(ty)?;
+                let addr = self.state.stack_alloc(align as u8, size);
+                if size > 0 {
+                    let value = BV::new_const(self.ctx, name, (size * 8) as u32);
+                    self.state.mem.store_bitvector(addr.clone(), value);
+                }
+                Ok(addr)
+            }
         }
     }
 
-    fn make_symbolic(&self, func: &FuncDef, param: &FuncParam) -> BV<'ctx> {
+    fn make_symbolic(&mut self, func: &FuncDef, param: &FuncParam) -> Result<BV<'ctx>, Error> {
         match param {
             FuncParam::Regular(ty, name) => {
                 self.symbolic_type(func.name.to_string() + ":" + name, ty)
             }
-            FuncParam::Env(_) => panic!("env parameters not supported"),
-            FuncParam::Variadic => panic!("varadic functions not supported"),
+            FuncParam::Env(_) => Ok(self.v.make_long(ENV_ADDR)),
+            // No variadic arguments are passed to the entry function.
+            FuncParam::Variadic => unreachable!(),
         }
     }
 
-    fn lookup_params(&self, params: &Vec<FuncParam>) -> Result<Vec<BV<'ctx>>, Error> {
+    // Aggregates are passed by reference to a copy in memory, the
+    // copy is allocated on the stack of the current function.
+    fn copy_aggregate(&mut self, ty: &str, src: BV<'ctx>) -> Result<BV<'ctx>, Error> {
+        let (size, align) = self.state.type_layout(ty)?;
+        let dest = self.state.stack_alloc(align as u8, size);
+        self.state.mem.copy(&dest, &src, size);
+        Ok(dest)
+    }
+
+    fn lookup_params(&mut self, params: &[FuncParam]) -> Result<Vec<BV<'ctx>>, Error> {
         let mut vec: Vec<BV<'ctx>> = Vec::new();
         for param in params.iter() {
             match param {
@@ -94,14 +403,23 @@ impl<'ctx, 'src> Interp<'ctx, 'src> {
 
                     // Calls with a sub-word return type define a temporary of
                     // base type `w` with its most significant bits unspecified.
-                    if let Type::SubWordType(swty) = ty {
-                        val = self.v.extend_subword(*swty, val)
+                    match ty {
+                        Type::SubWordType(swty) => val = self.v.extend_subword(*swty, val),
+                        Type::UserDef(name) => val = self.copy_aggregate(name, val)?,
+                        Type::Base(_) => {}
                     }
 
                     vec.push(val);
                 }
-                FuncParam::Env(_) => panic!("env parameters not supported"),
-                FuncParam::Variadic => panic!("varadic functions not supported"),
+                FuncParam::Env(name) => {
+                    let val = self
+                        .state
+                        .get_local(name)
+                        .ok_or(Error::UnknownVariable(name.to_string()))?;
+                    vec.push(val);
+                }
+                // Separates fixed and variadic arguments of the call.
+                FuncParam::Variadic => {}
             };
         }
 
@@ -115,15 +433,18 @@ impl<'ctx, 'src> Interp<'ctx, 'src> {
                 .state
                 .get_ptr(v)
                 .ok_or(Error::UnknownVariable(v.to_string())),
-            Const::SFP(_) => panic!("single precision floating points not supported"),
-            Const::DFP(_) => panic!("double precision floating points not supported"),
+            Const::SFP(f) => Ok(self.v.from_f32(*f)),
+            Const::DFP(f) => Ok(self.v.from_f64(*f)),
         }
     }
 
     fn get_dyn_const(&self, dconst: &DynConst) -> Result<BV<'ctx>, Error> {
         match dconst {
             DynConst::Const(c) => self.get_const(c),
-            DynConst::Thread(_) => panic!("thread-local constants not supported"),
+            DynConst::Thread(v) => self
+                .state
+                .get_tls_ptr(v)
+                .ok_or(Error::UnknownVariable(v.to_string())),
         }
     }
 
@@ -153,21 +474,15 @@ impl<'ctx, 'src> Interp<'ctx, 'src> {
     fn perform_compare(
         &self,
         dest_ty: BaseType,
+        ty: BaseType,
         op: &CmpOp,
         bv1: BV<'ctx>,
         bv2: BV<'ctx>,
     ) -> BV<'ctx> {
-        let cond = match op {
-            CmpOp::Eq => bv1._eq(&bv2),
-            CmpOp::Ne => bv1._eq(&bv2).not(),
-            CmpOp::Sle => bv1.bvsle(&bv2),
-            CmpOp::Slt => bv1.bvslt(&bv2),
-            CmpOp::Sge => bv1.bvsgt(&bv2),
-            CmpOp::Sgt => bv1.bvsgt(&bv2),
-            CmpOp::Ule => bv1.bvule(&bv2),
-            CmpOp::Ult => bv1.bvult(&bv2),
-            CmpOp::Uge => bv1.bvuge(&bv2),
-            CmpOp::Ugt => bv1.bvugt(&bv2),
+        let cond = if ValueFactory::is_float(ty) {
+            self.float_compare(op, &bv1, &bv2)
+        } else {
+            self.int_compare(op, &bv1, &bv2)
         };
 
         let true_bv = self.v.from_base_u64(dest_ty, 1);
@@ -176,6 +491,34 @@ impl<'ctx, 'src> Interp<'ctx, 'src> {
         cond.ite(&true_bv, &false_bv)
     }
 
+    fn int_compare(&self, op: &CmpOp, bv1: &BV<'ctx>, bv2: &BV<'ctx>) -> Bool<'ctx> {
+        match op {
+            CmpOp::Eq => bv1._eq(bv2),
+            CmpOp::Ne => bv1._eq(bv2).not(),
+            CmpOp::Sle => bv1.bvsle(bv2),
+            CmpOp::Slt => bv1.bvslt(bv2),
+            CmpOp::Sge => bv1.bvsgt(bv2),
+            CmpOp::Sgt => bv1.bvsgt(bv2),
+            CmpOp::Ule => bv1.bvule(bv2),
+            CmpOp::Ult => bv1.bvult(bv2),
+            CmpOp::Uge => bv1.bvuge(bv2),
+            CmpOp::Ugt => bv1.bvugt(bv2),
+        }
+    }
+
+    // Floating point comparisons are always ordered, hence
+    // signed and unsigned comparison operators are equivalent.
+    fn float_compare(&self, op: &CmpOp, bv1: &BV<'ctx>, bv2: &BV<'ctx>) -> Bool<'ctx> {
+        match op {
+            CmpOp::Eq => self.v.float_eq(bv1, bv2),
+            CmpOp::Ne => self.v.float_eq(bv1, bv2).not(),
+            CmpOp::Sle | CmpOp::Ule => self.v.float_le(bv1, bv2),
+            CmpOp::Slt | CmpOp::Ult => self.v.float_lt(bv1, bv2),
+            CmpOp::Sge | CmpOp::Uge => self.v.float_ge(bv1, bv2),
+            CmpOp::Sgt | CmpOp::Ugt => self.v.float_gt(bv1, bv2),
+        }
+    }
+
     pub fn perform_binop<F>(
         &self,
         dest_ty: BaseType,
@@ -191,16 +534,283 @@ impl<'ctx, 'src> Interp<'ctx, 'src> {
         Ok(op(&bv1, &bv2))
     }
 
+    // Like perform_binop, but checks whether the division can fault first.
+    fn perform_division<F>(
+        &mut self,
+        dest_ty: BaseType,
+        op: F,
+        signed: bool,
+        o1: &Value,
+        o2: &Value,
+    ) -> Result<BV<'ctx>, Error>
+    where
+        F: Fn(&BV<'ctx>, &BV<'ctx>) -> BV<'ctx>,
+    {
+        let bv1 = self.get_value(Some(dest_ty), o1)?;
+        let bv2 = self.get_value(Some(dest_ty), o2)?;
+
+        let zero = self.v.from_base_u64(dest_ty, 0);
+        self.check_fault("division by zero", bv2._eq(&zero))?;
+
+        // The result of INT_MIN / -1 is not representable.
+        if signed {
+            let min = self.v.from_base_u64(dest_ty, 1 << (bv1.get_size() - 1));
+            let minus_one = self.v.from_base_i64(dest_ty, -1);
+            let overflow = Bool::and(self.ctx, &[&bv1._eq(&min), &bv2._eq(&minus_one)]);
+            self.check_fault("signed division overflow", overflow)?;
+        }
+
+        Ok(op(&bv1, &bv2))
+    }
+
+    // Checks whether the given fault condition is satisfiable on the
+    // current path. If so, the fault is reported together with an input
+    // triggering it and execution continues on the non-faulting path.
+    pub fn check_fault(&mut self, fault: &str, cond: Bool<'ctx>) -> Result<(), Error> {
+        // Avoid querying the solver for accesses to concrete addresses.
+        let policy = self.opts.solver.unknown;
+        if cond.simplify().as_bool() == Some(false) || !feasible(&self.solver, &cond, policy)? {
+            return Ok(());
+        }
+
+        if self.reporting() {
+            writeln!(self.out, "[fault] Possible {}", fault).unwrap();
+            let input = self.solver.get_model().and_then(|m| {
+                self.inputs
+                    .iter()
+                    .map(|i| m.eval(i, true)?.as_u64())
+                    .collect::<Option<Vec<_>>>()
+            });
+            match input {
+                Some(values) => {
+                    let values: Vec<_> = values.iter().map(|v| v.to_string()).collect();
+                    writeln!(self.out, "Triggering input: ({})", values.join(", ")).unwrap();
+                }
+                None => writeln!(self.out, "No triggering input available").unwrap(),
+            }
+        }
+
+        // In concolic mode, the path must remain consistent with the
+        // concrete input. Hence, it ends if the input triggers the fault.
+        // The fault condition is recorded nonetheless, allowing it to be
+        // negated in order to generate an input avoiding the fault.
+        if let Some(mut assumptions) = self.input_assumptions() {
+            assumptions.push(cond.clone());
+            if self.solver.check_assumptions(&assumptions) == SatResult::Sat {
+                self.add_constraint(cond);
+                return Err(Error::Fault(fault.to_string()));
+            }
+        }
+
+        let safe = cond.not();
+        if !feasible(&self.solver, &safe, policy)? {
+            return Err(Error::Fault(fault.to_string()));
+        }
+        self.add_constraint(safe);
+
+        Ok(())
+    }
+
+    // Checks whether accessing n bytes at the given address may access the
+    // null page, freed heap memory, popped stack memory, or memory outside
+    // of all allocated objects.
+    pub fn check_access(&mut self, addr: &BV<'ctx>, n: u64) -> Result<(), Error> {
+        self.check_access_if(&Bool::from_bool(self.ctx, true), addr, n)
+    }
+
+    // Like check_access, but the memory is only accessed if the given
+    // condition holds, e.g. if the preceding bytes of a string are non-zero.
+    pub fn check_access_if(
+        &mut self,
+        guard: &Bool<'ctx>,
+        addr: &BV<'ctx>,
+        n: u64,
+    ) -> Result<(), Error> {
+        let null = addr.bvult(&self.v.make_long(NULL_PAGE_SIZE));
+        let null = Bool::and(self.ctx, &[guard, &null]);
+        self.check_fault("null pointer dereference", null)?;
+
+        // Addresses of popped stack objects may be reused by live objects.
+        let valid = self.state.mem.in_bounds(addr, n);
+        let freed = self.state.mem.in_dead(addr, n, ObjectKind::Heap);
+        let freed = Bool::and(self.ctx, &[guard, &freed, &valid.not()]);
+        self.check_fault("use after free", freed)?;
+        let popped = self.state.mem.in_dead(addr, n, ObjectKind::Stack);
+        let popped = Bool::and(self.ctx, &[guard, &popped, &valid.not()]);
+        self.check_fault("use of dangling stack pointer", popped)?;
+
+        let invalid = Bool::and(self.ctx, &[guard, &valid.not()]);
+        self.check_fault("out-of-bounds memory access", invalid)
+    }
+
+    ////
+    // Uninitialized values
+    ////
+
+    // Condition under which the value depends on uninitialized memory.
+    fn undef(&self, value: &Value) -> Option<Bool<'ctx>> {
+        match value {
+            Value::LocalVar(var) => self.state.get_undef(var),
+            Value::Const(_) => None,
+        }
+    }
+
+    // Condition under which the result of the instruction depends on
+    // uninitialized memory, either through its operands or, for loads,
+    // through the loaded bytes. Must be called after executing it.
+    fn inst_undef(&self, inst: &Instr) -> Result<Option<Bool<'ctx>>, Error> {
+        let operands = match inst {
+            Instr::Add(v1, v2)
+            | Instr::Sub(v1, v2)
+            | Instr::Mul(v1, v2)
+            | Instr::UDiv(v1, v2)
+            | Instr::Rem(v1, v2)
+            | Instr::URem(v1, v2)
+            | Instr::Or(v1, v2)
+            | Instr::Xor(v1, v2)
+            | Instr::And(v1, v2)
+            | Instr::Sar(v1, v2)
+            | Instr::Shr(v1, v2)
+            | Instr::Shl(v1, v2)
+            | Instr::Compare(_, _, v1, v2) => vec![v1, v2],
+            Instr::Neg(v) | Instr::Ext(_, v) => vec![v],
+            Instr::Alloc(..) => vec![],
+            Instr::Load(ty, a) => {
+                let size = ValueFactory::loadty_to_size(*ty) as u64 / 8;
+                let addr = self.get_value(None, a)?;
+                return Ok(Some(self.state.mem.uninit(&addr, size)));
+            }
+        };
+
+        let conds: Vec<_> = operands.into_iter().filter_map(|v| self.undef(v)).collect();
+        if conds.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Bool::or(self.ctx, &conds.iter().collect::<Vec<_>>())))
+    }
+
+    // Conditions under which the arguments of a call depend on
+    // uninitialized memory. Aggregates are passed as a pointer
+    // to a copy, hence the pointer itself is always initialized.
+    fn params_undef(&self, params: &[FuncParam]) -> Vec<Option<Bool<'ctx>>> {
+        params
+            .iter()
+            .filter_map(|param| match param {
+                FuncParam::Regular(Type::UserDef(_), _) => Some(None),
+                FuncParam::Regular(_, name) | FuncParam::Env(name) => {
+                    Some(self.state.get_undef(name))
+                }
+                FuncParam::Variadic => None,
+            })
+            .collect()
+    }
+
+    // Reports if the value may depend on uninitialized memory.
+    fn check_undef(&mut self, fault: &str, value: &Value) -> Result<(), Error> {
+        match self.undef(value) {
+            Some(cond) => self.check_fault(fault, cond),
+            None => Ok(()),
+        }
+    }
+
+    ////
+    // Interface for native function models, see libc.rs
+    ////
+
+    pub fn ctx(&self) -> &'ctx Context {
+        self.ctx
+    }
+
+    pub fn state(&mut self) -> &mut State<'ctx, 'src> {
+        &mut self.state
+    }
+
+    // Writes a line to the output of the current path.
+    pub fn print(&mut self, line: &str) {
+        if self.reporting() {
+            writeln!(self.out, "{}", line).unwrap();
+        }
+    }
+
+    pub fn size_policy(&self) -> SizePolicy {
+        self.opts.alloc_size
+    }
+
+    pub fn is_feasible(&self, cond: &Bool<'ctx>) -> Result<bool, Error> {
+        feasible(&self.solver, cond, self.opts.solver.unknown)
+    }
+
+    // Whether the condition holds for all inputs satisfying the path
+    // constraints. Escaping stack addresses are only reported for values
+    // which are stack addresses on every path, as integers which merely
+    // may equal a stack address (e.g. symbolic parameters) are common.
+    fn always(&self, cond: &Bool<'ctx>) -> Result<bool, Error> {
+        if let Some(b) = cond.simplify().as_bool() {
+            return Ok(b);
+        }
+        Ok(!self.is_feasible(&cond.not())?)
+    }
+
+    // Returns a value of the bitvector satisfying the path constraints.
+    pub fn eval(&mut self, bv: &BV<'ctx>) -> Result<u64, Error> {
+        if let Some(v) = bv.simplify().as_u64() {
+            return Ok(v);
+        }
+
+        let model = match self.solver.check() {
+            SatResult::Sat => self.solver.get_model(),
+            SatResult::Unsat => None,
+            SatResult::Unknown => return Err(Error::SolverUnknown),
+        };
+        model
+            .and_then(|m| m.eval(bv, true)?.as_u64())
+            .ok_or(Error::SolverUnknown)
+    }
+
+    // Like eval, but also constrains the path to the returned value.
+    pub fn concretize(&mut self, bv: &BV<'ctx>) -> Result<u64, Error> {
+        let v = self.eval(bv)?;
+        let value = BV::from_u64(self.ctx, v, bv.get_size());
+        if bv.simplify() != value {
+            self.add_constraint(bv._eq(&value));
+        }
+        Ok(v)
+    }
+
+    fn add_constraint(&mut self, cond: Bool<'ctx>) {
+        self.solver.push();
+        self.solver.assert(&cond);
+        self.constraints.push(cond);
+    }
+
+    fn exec_float_inst(&self, dest_ty: BaseType, inst: &Instr) -> Result<BV<'ctx>, Error> {
+        let v = &self.v;
+        match inst {
+            Instr::Add(v1, v2) => self.perform_binop(dest_ty, |a, b| v.float_add(a, b), v1, v2),
+            Instr::Sub(v1, v2) => self.perform_binop(dest_ty, |a, b| v.float_sub(a, b), v1, v2),
+            Instr::Mul(v1, v2) => self.perform_binop(dest_ty, |a, b| v.float_mul(a, b), v1, v2),
+            Instr::Neg(val) => {
+                let bv = self.get_value(Some(dest_ty), val)?;
+                Ok(v.float_neg(&bv))
+            }
+            _ => Err(Error::InvalidFloatInstr),
+        }
+    }
+
     fn exec_inst(&mut self, dest_ty: BaseType, inst: &Instr) -> Result<BV<'ctx>, Error> {
         // XXX: This instruction simulator assumes that the instructions are
         // well-typed. If not, this causes dubious assertion failures everywhere.
+        if ValueFactory::is_float(dest_ty) && !matches!(inst, Instr::Load(..)) {
+            return self.exec_float_inst(dest_ty, inst);
+        }
+
         match inst {
             Instr::Add(v1, v2) => self.perform_binop(dest_ty, BV::bvadd, v1, v2),
             Instr::Sub(v1, v2) => self.perform_binop(dest_ty, BV::bvsub, v1, v2),
             Instr::Mul(v1, v2) => self.perform_binop(dest_ty, BV::bvmul, v1, v2),
-            Instr::UDiv(v1, v2) => self.perform_binop(dest_ty, BV::bvudiv, v1, v2),
-            Instr::Rem(v1, v2) => self.perform_binop(dest_ty, BV::bvsrem, v1, v2),
-            Instr::URem(v1, v2) => self.perform_binop(dest_ty, BV::bvurem, v1, v2),
+            Instr::UDiv(v1, v2) => self.perform_division(dest_ty, BV::bvudiv, false, v1, v2),
+            Instr::Rem(v1, v2) => self.perform_division(dest_ty, BV::bvsrem, true, v1, v2),
+            Instr::URem(v1, v2) => self.perform_division(dest_ty, BV::bvurem, false, v1, v2),
             Instr::Or(v1, v2) => self.perform_binop(dest_ty, BV::bvor, v1, v2),
             Instr::Xor(v1, v2) => self.perform_binop(dest_ty, BV::bvxor, v1, v2),
             Instr::And(v1, v2) => self.perform_binop(dest_ty, BV::bvand, v1, v2),
@@ -215,6 +825,8 @@ impl<'ctx, 'src> Interp<'ctx, 'src> {
                 let size = ValueFactory::loadty_to_size(*ty);
                 assert!(size % 8 == 0);
                 let addr = self.get_value(None, a)?;
+                self.check_undef("use of uninitialized value as address", a)?;
+                self.check_access(&addr, size as u64 / 8)?;
                 let value = self.state.mem.load_bitvector(addr, size as u64 / 8);
 
                 // For types smaller than long, two variants of the load
@@ -237,7 +849,7 @@ impl<'ctx, 'src> Interp<'ctx, 'src> {
             Instr::Compare(ty, op, v1, v2) => {
                 let bv1 = self.get_value(Some(*ty), v1)?;
                 let bv2 = self.get_value(Some(*ty), v2)?;
-                Ok(self.perform_compare(dest_ty, op, bv1, bv2))
+                Ok(self.perform_compare(dest_ty, *ty, op, bv1, bv2))
             }
             Instr::Ext(ty, v) => {
                 let bv = self.get_value(None, v)?;
@@ -256,40 +868,157 @@ impl<'ctx, 'src> Interp<'ctx, 'src> {
             VolatileInstr::Store(ty, v, a) => {
                 let value = self.get_value(None, v)?;
                 let addr = self.get_value(None, a)?;
-                self.state
-                    .mem
-                    .store_bitvector(addr, self.v.cast_to(*ty, value));
+                self.check_undef("use of uninitialized value as address", a)?;
+
+                let value = self.v.cast_to(*ty, value);
+                let n = value.get_size() as u64 / 8;
+                self.check_access(&addr, n)?;
+
+                // Stack objects must not outlive their stack frame.
+                if value.get_size() == LONG_SIZE {
+                    let stack = self.state.mem.in_object(&value, 1, ObjectKind::Stack);
+                    if self.always(&stack)? {
+                        let global = self.state.mem.in_object(&addr, n, ObjectKind::Data);
+                        self.check_fault("store of stack address into global", global)?;
+                    }
+                }
+                self.state.mem.store_bitvector(addr.clone(), value);
+
+                // Storing an uninitialized value leaves memory uninitialized.
+                if let Some(cond) = self.undef(v) {
+                    self.state.mem.set_init(&addr, n, &cond.not());
+                }
+            }
+            VolatileInstr::Blit(src, dst, n) => {
+                self.check_undef("use of uninitialized value as address", src)?;
+                self.check_undef("use of uninitialized value as address", dst)?;
+                let src = self.get_value(Some(BaseType::Long), src)?;
+                let dst = self.get_value(Some(BaseType::Long), dst)?;
+
+                if *n > 0 {
+                    self.check_access(&src, *n)?;
+                    self.check_access(&dst, *n)?;
+                    self.state.mem.copy(&dst, &src, *n);
+                }
             }
-            _ => todo!(),
         }
 
         Ok(())
     }
 
-    fn exec_stat(&mut self, stat: &'src Statement) -> Result<(), Error> {
+    // Returns Some if execution of the current block can't continue with the
+    // next statement, e.g. if a new stack frame has been pushed for a call.
+    fn exec_stat(
+        &mut self,
+        stat: &'src Statement,
+    ) -> Result<Option<BlockReturn<'ctx, 'src>>, Error> {
         match stat {
             Statement::Assign(dest, base, inst) => {
                 let result = self.exec_inst(*base, &inst)?;
+                let undef = self.inst_undef(&inst)?;
                 self.state.add_local(dest, result);
+                if let Some(cond) = undef {
+                    self.state.set_undef(dest, cond);
+                }
             }
             Statement::Volatile(instr) => {
                 self.exec_volatile(instr)?;
             }
-            Statement::Call(dest, _ty, fname, params) => {
+            Statement::Call(dest, ty, fname, params) => {
+                let func = match self.state.get_func(fname) {
+                    Some(func) => func,
+                    None if self.state.get_local(fname).is_some() => {
+                        match self.resolve_call(fname)? {
+                            Callee::Func(func) => func,
+                            Callee::Fork(path1, path2) => {
+                                // Resolve the call again on both paths.
+                                self.state.rewind_stat();
+                                return Ok(Some(BlockReturn::Fork(path1, path2)));
+                            }
+                        }
+                    }
+                    None => {
+                        let model =
+                            libc::lookup(fname).ok_or(Error::UnknownFunction(fname.to_string()))?;
+                        let values = self.lookup_params(params)?;
+                        match model(self, &values)? {
+                            libc::Return::Value(Some(mut value)) => {
+                                if let Type::Base(ty) = ty {
+                                    value = self.v.cast_to(ExtType::Base(*ty), value);
+                                }
+                                self.state.add_local(dest, value);
+                            }
+                            libc::Return::Value(None) => {}
+                            libc::Return::Fork(cond) => {
+                                // Execute the call again on both paths.
+                                self.state.rewind_stat();
+                                let other = cond.not();
+                                return Ok(Some(BlockReturn::Fork(
+                                    Target(Some(cond), None),
+                                    Target(Some(other), None),
+                                )));
+                            }
+                        }
+                        return Ok(None);
+                    }
+                };
+
                 let values = self.lookup_params(params)?;
-                let func = self
-                    .state
-                    .get_func(fname)
-                    .ok_or(Error::UnknownFunction(fname.to_string()))?;
-
-                let result = self.exec_func(func, values)?;
-                if let Some(ret_val) = result {
-                    self.state.add_local(dest, ret_val);
+                let undefs = self.params_undef(params);
+                self.push_func(func, values, Some((dest.as_str(), ty)))?;
+                for (param, undef) in func.params.iter().zip(undefs.into_iter()) {
+                    if let (Some(name), Some(cond)) = (param.get_name(), undef) {
+                        self.state.set_undef(name, cond);
+                    }
                 }
+                return Ok(Some(BlockReturn::Call));
             }
         }
 
-        Ok(())
+        Ok(None)
+    }
+
+    // Resolves a call through the function pointer stored in the given
+    // variable. If the pointer may point to multiple functions, execution
+    // forks: on one path the pointer points to the first of these functions
+    // and on the other path it doesn't. The call is then resolved again.
+    fn resolve_call(&mut self, var: &str) -> Result<Callee<'ctx, 'src>, Error> {
+        let ptr = self
+            .state
+            .get_local(var)
+            .ok_or(Error::UnknownVariable(var.to_string()))?;
+        let funcs = self.state.get_funcs();
+
+        let conds: Vec<_> = funcs.iter().map(|(addr, _)| ptr._eq(addr)).collect();
+        let invalid: Vec<_> = conds.iter().map(|c| c.not()).collect();
+        let invalid = Bool::and(self.ctx, &invalid.iter().collect::<Vec<_>>());
+        self.check_fault("call through invalid function pointer", invalid)?;
+
+        for ((_, func), cond) in funcs.into_iter().zip(conds.into_iter()) {
+            if !self.is_feasible(&cond)? {
+                continue;
+            } else if self.is_feasible(&cond.not())? {
+                let other = cond.not();
+                return Ok(Callee::Fork(
+                    Target(Some(cond), None),
+                    Target(Some(other), None),
+                ));
+            }
+
+            if self.reporting() {
+                writeln!(
+                    self.out,
+                    "[call] Calling function '{}' indirectly",
+                    func.name
+                )
+                .unwrap();
+            }
+            return Ok(Callee::Func(func));
+        }
+
+        Err(Error::Fault(
+            "call through invalid function pointer".to_string(),
+        ))
     }
 
     fn get_block(&self, label: &str) -> Result<&'src Block, Error> {
@@ -298,23 +1027,25 @@ impl<'ctx, 'src> Interp<'ctx, 'src> {
             .ok_or(Error::UnknownLabel(label.to_string()))
     }
 
-    fn exec_jump(&self, instr: &JumpInstr) -> Result<FuncReturn<'ctx, 'src>, Error> {
+    fn exec_jump(&mut self, instr: &JumpInstr) -> Result<FuncReturn<'ctx, 'src>, Error> {
         match instr {
             JumpInstr::Jump(label) => {
-                let path = Path(None, self.get_block(label)?);
-                Ok(FuncReturn::Jump(path))
+                let target = Target(None, Some(self.get_block(label)?));
+                Ok(FuncReturn::Jump(target))
             }
             JumpInstr::Jnz(value, nzero_label, zero_label) => {
+                self.check_undef("branch on uninitialized value", value)?;
                 let bv = self.get_value(Some(BaseType::Word), value)?;
 
                 assert!(bv.get_size() == WORD_SIZE);
                 let is_zero = bv._eq(&self.v.make_word(0));
 
-                let nzero_path = Path(Some(is_zero.not()), self.get_block(nzero_label)?);
-                let zero_path = Path(Some(is_zero.clone()), self.get_block(zero_label)?);
+                let nzero_path = Target(Some(is_zero.not()), Some(self.get_block(nzero_label)?));
+                let zero_path = Target(Some(is_zero.clone()), Some(self.get_block(zero_label)?));
 
-                let zero_feasible = zero_path.feasible(&self.solver);
-                if zero_feasible && nzero_path.feasible(&self.solver) {
+                let policy = self.opts.solver.unknown;
+                let zero_feasible = zero_path.feasible(&self.solver, policy)?;
+                if zero_feasible && nzero_path.feasible(&self.solver, policy)? {
                     Ok(FuncReturn::CondJump(nzero_path, zero_path))
                 } else if zero_feasible {
                     Ok(FuncReturn::Jump(zero_path))
@@ -323,29 +1054,39 @@ impl<'ctx, 'src> Interp<'ctx, 'src> {
                 }
             }
             JumpInstr::Return(opt_val) => match opt_val {
-                Some(x) => Ok(FuncReturn::Return(Some(self.get_value(None, x)?))),
+                Some(x) => {
+                    self.check_undef("return of uninitialized value", x)?;
+                    Ok(FuncReturn::Return(Some(self.get_value(None, x)?)))
+                }
                 None => Ok(FuncReturn::Return(None)),
             },
             JumpInstr::Halt => {
-                println!("Halting executing");
+                writeln!(self.out, "Halting executing").unwrap();
                 Err(Error::HaltExecution)
             }
         }
     }
 
-    #[inline]
-    fn explore_path(&mut self, path: &Path<'ctx, 'src>) -> Result<BlockReturn<'ctx, 'src>, Error> {
-        println!("[jnz] Exploring path for label '{}'", path.1.label);
+    fn enter_block(&mut self, block: &'src Block) -> Result<(), Error> {
+        self.state.set_block(block);
 
-        if let Some(c) = &path.0 {
-            self.solver.assert(c);
+        let func = self.state.get_cur_func();
+        let id = (func.name.as_str(), block.label.as_str());
+        self.coverage.insert(id);
+
+        let visits = self.stats.visits.entry(id).or_insert(0);
+        *visits += 1;
+        if let Some(max) = self.opts.limits.max_visits {
+            if *visits > max {
+                return Err(Error::PathTruncated(format!(
+                    "block '{}' visited more than {} times",
+                    block.label, max
+                )));
+            }
         }
-        self.exec_block(path.1)
-    }
 
-    fn exec_block(&mut self, block: &'src Block) -> Result<BlockReturn<'ctx, 'src>, Error> {
         for phi in block.phi.iter() {
-            match self.prev_label {
+            match self.state.get_prev_label() {
                 Some(label) => {
                     let val = phi
                         .labels
@@ -353,14 +1094,66 @@ impl<'ctx, 'src> Interp<'ctx, 'src> {
                         .ok_or(Error::UnknownLabel(label.to_string()))?;
 
                     let bv = self.get_value(Some(phi.base_type), val)?;
+                    let undef = self.undef(val);
                     self.state.add_local(&phi.ident, bv);
+                    if let Some(cond) = undef {
+                        self.state.set_undef(&phi.ident, cond);
+                    }
                 }
                 None => return Err(Error::PhiAtFuncStart),
             }
         }
 
-        for stat in block.inst.iter() {
-            self.exec_stat(stat)?;
+        Ok(())
+    }
+
+    #[inline]
+    fn explore_path(&mut self, target: Target<'ctx, 'src>) -> Result<(), Error> {
+        let block = match target.1 {
+            Some(block) => block,
+            None => {
+                if let Some(c) = target.0 {
+                    self.add_constraint(c);
+                }
+                return Ok(());
+            }
+        };
+
+        // Don't report paths again that are only replayed.
+        if self.reporting() {
+            let label = &block.label;
+            writeln!(self.out, "[jnz] Exploring path for label '{}'", label).unwrap();
+        }
+
+        if let Some(c) = target.0 {
+            self.add_constraint(c);
+        }
+        self.enter_block(block)
+    }
+
+    // Whether the current path is reported, i.e. not only replayed.
+    fn reporting(&self) -> bool {
+        self.replay.is_empty() || self.report_replay
+    }
+
+    // Executes the remaining statements of the current block. Execution
+    // stops at the jump instruction or if a function call is encountered.
+    fn exec_block(&mut self) -> Result<BlockReturn<'ctx, 'src>, Error> {
+        let block = self.state.get_cur_block();
+        while let Some(stat) = self.state.next_stat() {
+            self.stats.insts += 1;
+            if let Some(max) = self.opts.limits.max_insts {
+                if self.stats.insts > max {
+                    return Err(Error::PathTruncated(format!(
+                        "executed more than {} instructions",
+                        max
+                    )));
+                }
+            }
+
+            if let Some(ret) = self.exec_stat(stat)? {
+                return Ok(ret);
+            }
         }
 
         let jump = match &block.jump {
@@ -369,122 +1162,585 @@ impl<'ctx, 'src> Interp<'ctx, 'src> {
         };
 
         let targets = self.exec_jump(jump)?;
-        self.prev_label = Some(&block.label);
-        match targets {
-            // For conditional jumps, we fork(3) the entire interpreter process.
-            // This is, obviously, horribly inefficient and will lead to memory
-            // explosion issues for any somewhat complex program. In the future,
-            // the State module should be modified to allow efficient copies of
-            // the state by leveraging a copy-on-write mechanism.
-            FuncReturn::CondJump(path1, path2) => unsafe {
-                let pid = fork();
-                match pid {
-                    -1 => Err(Error::ForkFailed),
-                    0 => self.explore_path(&path1),
-                    _ => {
-                        let mut status = 0 as c_int;
-                        if waitpid(pid, &mut status as *mut c_int, 0) == -1 {
-                            Err(Error::WaitpidFailed)
-                        } else {
-                            if status != 0 {
-                                exit(status);
-                            }
-                            self.explore_path(&path2)
+        self.state.set_prev_label(&block.label);
+        Ok(BlockReturn::Jump(targets))
+    }
+
+    // Advances execution of the current path by a single block. For
+    // conditional jumps, where both targets are feasible, the current
+    // path is split into two pending paths which are returned to the
+    // caller. Otherwise, an empty vector is returned.
+    fn step(&mut self) -> Result<Vec<Path<'ctx, 'src>>, Error> {
+        if self.at_join_point() {
+            return Ok(vec![self.suspend()]);
+        }
+
+        match self.exec_block()? {
+            BlockReturn::Call => Ok(Vec::new()),
+            BlockReturn::Fork(path1, path2) => self.branch(path1, path2),
+            BlockReturn::Fallthrough(label) => {
+                let func = self.state.get_cur_func();
+                let mut it = func.body.iter();
+                let cur = it.find(|b| b.label == label);
+                assert!(cur.is_some() && cur.unwrap().label == label);
+
+                // Last block is not terminated by a jump instruction.
+                let next = it.next().ok_or(Error::MissingJump)?;
+                self.state.set_prev_label(label);
+                self.enter_block(next)?;
+                Ok(Vec::new())
+            }
+            BlockReturn::Jump(targets) => match targets {
+                FuncReturn::CondJump(path1, path2) => self.branch(path1, path2),
+                FuncReturn::Jump(path) => {
+                    self.explore_path(path)?;
+                    Ok(Vec::new())
+                }
+                FuncReturn::Return(value) => {
+                    // TODO: Treat return from entry point function like `hlt` for now.
+                    if self.state.stack_size() == 1 {
+                        return Err(Error::HaltExecution);
+                    }
+
+                    // Pointers into the stack frame of the callee dangle after
+                    // returning, except for aggregates which are copied.
+                    let mut escapes = None;
+                    if let Some(v) = value.as_ref().filter(|v| v.get_size() == LONG_SIZE) {
+                        let stack = self.state.mem.in_object(v, 1, ObjectKind::Stack);
+                        if self.always(&stack)? {
+                            escapes = Some(self.state.in_frame(v));
                         }
                     }
+
+                    let dest = self.state.pop_func();
+                    let aggregate = matches!(dest, Some((_, Type::UserDef(_))));
+                    if let (Some(cond), false) = (escapes, aggregate) {
+                        self.check_fault("return of stack address", cond)?;
+                    }
+
+                    if let (Some((dest, ty)), Some(mut ret_val)) = (dest, value) {
+                        // The returned aggregate may reside in the stack
+                        // frame of the callee, hence it needs to be copied.
+                        if let Type::UserDef(name) = ty {
+                            ret_val = self.copy_aggregate(name, ret_val)?;
+                        }
+                        self.state.add_local(dest, ret_val);
+                    }
+                    Ok(Vec::new())
                 }
             },
-            FuncReturn::Jump(path) => self.explore_path(&path),
-            FuncReturn::Return(value) => {
-                // TODO: Treat return from entry point function like `hlt` for now.
-                if self.state.stack_size() == 1 {
-                    Err(Error::HaltExecution)
-                } else {
-                    Ok(BlockReturn::Value(value))
-                }
+        }
+    }
+
+    // Takes a branch decision between both targets. The decision is either
+    // replayed, determined by the concrete input, or both targets are
+    // returned as pending paths. The first target corresponds to `true`.
+    fn branch(
+        &mut self,
+        path1: Target<'ctx, 'src>,
+        path2: Target<'ctx, 'src>,
+    ) -> Result<Vec<Path<'ctx, 'src>>, Error> {
+        self.stats.branches += 1;
+        if let Some(max) = self.opts.limits.max_branches {
+            if self.stats.branches > max {
+                return Err(Error::PathTruncated(format!(
+                    "took more than {} branches",
+                    max
+                )));
+            }
+        }
+
+        let decision = self
+            .replay
+            .pop_front()
+            .or_else(|| self.follow_input(&path1));
+        match decision {
+            Some(nonzero) => {
+                self.trace.push(nonzero);
+                self.explore_path(if nonzero { path1 } else { path2 })?;
+                Ok(Vec::new())
+            }
+            None => {
+                let mut paths = vec![self.fork(path1, true), self.fork(path2, false)];
+                paths[0].out = std::mem::take(&mut self.out);
+                Ok(paths)
+            }
+        }
+    }
+
+    fn fork(&self, target: Target<'ctx, 'src>, nonzero: bool) -> Path<'ctx, 'src> {
+        let mut trace = self.trace.clone();
+        trace.push(nonzero);
+
+        Path {
+            state: self.state.clone(),
+            constraints: self.constraints.clone(),
+            stats: self.stats.clone(),
+            trace,
+            out: String::new(),
+            target: Some(target),
+        }
+    }
+
+    // In concolic mode, determine whether the concrete input
+    // takes the non-zero target of a conditional jump.
+    fn follow_input(&self, nzero_path: &Target<'ctx, 'src>) -> Option<bool> {
+        let mut assumptions = self.input_assumptions()?;
+        assumptions.extend(nzero_path.0.clone());
+
+        let r = self.solver.check_assumptions(&assumptions);
+        Some(r == SatResult::Sat)
+    }
+
+    // In concolic mode, assumptions fixing the inputs to their concrete values.
+    fn input_assumptions(&self) -> Option<Vec<Bool<'ctx>>> {
+        let values = self.concrete.as_ref()?;
+        let assumptions = self
+            .inputs
+            .iter()
+            .zip(values.iter())
+            .map(|(i, v)| i._eq(&BV::from_u64(self.ctx, *v, i.get_size())))
+            .collect();
+        Some(assumptions)
+    }
+
+    // Whether the current path has just entered a join point and
+    // should be suspended in order to merge it with other paths.
+    fn at_join_point(&mut self) -> bool {
+        let joins = match &self.join_points {
+            Some(j) => j,
+            None => return false,
+        };
+        if std::mem::take(&mut self.resumed_merged) || self.state.get_pc() != 0 {
+            return false;
+        }
+
+        let func = self.state.get_cur_func();
+        let block = self.state.get_cur_block();
+        joins.contains(&(func.name.as_str(), block.label.as_str()))
+    }
+
+    fn suspend(&mut self) -> Path<'ctx, 'src> {
+        Path {
+            state: self.state.clone(),
+            constraints: self.constraints.clone(),
+            stats: self.stats.clone(),
+            trace: self.trace.clone(),
+            out: std::mem::take(&mut self.out),
+            target: None,
+        }
+    }
+
+    // Each constraint is asserted in a separate solver scope. When switching
+    // to a different path, only the scopes of constraints which are not
+    // shared with the new path are popped. Thereby, the solver's learned
+    // state is reused across sibling paths.
+    fn sync_solver(&mut self, constraints: Vec<Bool<'ctx>>) {
+        let common = self
+            .constraints
+            .iter()
+            .zip(constraints.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let stale = self.constraints.len() - common;
+        if stale > 0 {
+            self.solver.pop(stale as u32);
+        }
+        for c in constraints[common..].iter() {
+            self.solver.push();
+            self.solver.assert(c);
+        }
+
+        self.constraints = constraints;
+    }
+
+    // Restores the interpreter state from a pending path.
+    fn resume(&mut self, path: Path<'ctx, 'src>) -> Result<(), Error> {
+        self.state = path.state;
+        self.sync_solver(path.constraints);
+        self.stats = path.stats;
+        self.trace = path.trace;
+        self.out.push_str(&path.out);
+
+        match path.target {
+            Some(target) => self.explore_path(target),
+            None => {
+                self.resumed_merged = true;
+                Ok(())
             }
         }
     }
 
-    pub fn exec_func(
+    // Discard the current path and restart execution from the given state.
+    fn restart(&mut self, state: State<'ctx, 'src>) {
+        self.state = state;
+        self.sync_solver(Vec::new());
+        self.stats = PathStats::default();
+        self.trace.clear();
+    }
+
+    fn push_func(
         &mut self,
         func: &'src FuncDef,
         params: Vec<BV<'ctx>>,
-    ) -> Result<Option<BV<'ctx>>, Error> {
-        self.state.push_func(func);
-
-        if func.params.len() != params.len() {
+        ret_dest: Option<(&'src str, &'src Type)>,
+    ) -> Result<(), Error> {
+        // Arguments exceeding the fixed parameters of a variadic
+        // function are passed as variadic arguments.
+        let variadic = matches!(func.params.last(), Some(FuncParam::Variadic));
+        let fixed = func.params.len() - variadic as usize;
+        if params.len() < fixed || (!variadic && params.len() != fixed) {
             return Err(Error::InvalidCall);
         }
-        for i in 0..func.params.len() {
-            let name = func.params[i].get_name().unwrap();
-            let bv = params[i].clone();
+        let entry = func.body.first().ok_or(Error::MissingJump)?;
+
+        let mut params = params;
+        let varargs = params.split_off(fixed);
+        self.state.push_func(func, entry, ret_dest, varargs);
+        for (param, bv) in func.params.iter().zip(params.into_iter()) {
+            let name = param.get_name().unwrap();
             self.state.add_local(name, bv);
         }
 
-        let mut block = func.body.first();
-        while block.is_some() {
-            // .unwrap() won't panic due to loop condition.
-            let cur_block = block.unwrap();
+        self.enter_block(entry)
+    }
 
-            match self.exec_block(cur_block) {
-                Err(Error::HaltExecution) => {
-                    self.dump();
-                    return Ok(None);
+    // Symbolically executes the function with the given name. Execution
+    // starts with replaying the given branch decisions (prefixes), one
+    // prefix after another, before forking at conditional jumps. This
+    // allows re-deriving the state of pending paths in a different
+    // interpreter instance (e.g. with a different context). If no prefix
+    // is given, all paths of the function are explored.
+    pub fn exec_symbolic(
+        &mut self,
+        name: &str,
+        prefixes: Vec<Vec<bool>>,
+        strategy: &mut dyn SearchStrategy<'ctx, 'src>,
+    ) -> Result<(), Error> {
+        let func = self
+            .state
+            .get_func(name)
+            .ok_or(Error::UnknownFunction(name.to_string()))?;
+
+        let init = self.state.clone();
+        let mut prefixes = prefixes;
+        self.start(func, &init, prefixes.pop().unwrap_or_default())?;
+
+        let mut pool: Vec<Path<'ctx, 'src>> = Vec::new();
+        'explore: loop {
+            let paths = match self.step() {
+                Ok(paths) if paths.is_empty() => continue,
+                Ok(paths) => paths,
+                Err(x) => {
+                    self.end_path(x)?;
+                    Vec::new()
                 }
-                Err(x) => return Err(x),
-                Ok(r) => match r {
-                    BlockReturn::Value(v) => {
-                        self.state.pop_func();
-                        return Ok(v);
-                    }
-                    BlockReturn::Fallthrough(label) => {
-                        let mut it = func.body.iter();
-                        let cur = it.find(|b| b.label == label);
-                        assert!(cur.is_some() && cur.unwrap().label == label);
+            };
 
-                        block = it.next();
-                        self.prev_label = Some(label);
+            // Suspended paths are kept in the merge pool until all
+            // other paths have been explored as far as possible.
+            let (suspended, paths): (Vec<_>, Vec<_>) =
+                paths.into_iter().partition(|p| p.target.is_none());
+            for path in suspended {
+                self.add_to_pool(&mut pool, path);
+            }
+
+            strategy.add(paths);
+            if self.checkpoint_due() {
+                self.write_checkpoint(&prefixes, strategy)?;
+            }
+
+            loop {
+                let result = match strategy.next(&self.coverage) {
+                    Some(path) => self.resume(path),
+                    None if !pool.is_empty() => {
+                        strategy.add(std::mem::take(&mut pool));
                         continue;
                     }
-                },
+                    None => match prefixes.pop() {
+                        Some(prefix) => self.start(func, &init, prefix),
+                        None => break 'explore,
+                    },
+                };
+                match result {
+                    Ok(()) => break,
+                    Err(x) => self.end_path(x)?,
+                }
             }
         }
 
-        // Last block is not terminated by a jump instruction.
-        Err(Error::MissingJump)
+        if self.checkpoint.is_some() {
+            self.write_checkpoint(&prefixes, strategy)?;
+        }
+        Ok(())
     }
 
-    pub fn exec_symbolic(&mut self, name: &String) -> Result<(), Error> {
-        let func = self
-            .state
-            .get_func(name)
-            .ok_or(Error::UnknownFunction(name.to_string()))?;
+    // Start execution of the given function from the initial state,
+    // replaying the given branch decisions.
+    fn start(
+        &mut self,
+        func: &'src FuncDef,
+        init: &State<'ctx, 'src>,
+        prefix: Vec<bool>,
+    ) -> Result<(), Error> {
+        self.restart(init.clone());
+        self.replay = prefix.into();
 
         let params = func
             .params
             .iter()
+            .filter(|p| !matches!(p, FuncParam::Variadic))
             .map(|p| self.make_symbolic(func, p))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Pointers to the environment and to aggregates are not inputs,
+        // they always point to the same (symbolic) memory region.
+        self.inputs = func
+            .params
+            .iter()
+            .zip(params.iter())
+            .filter(|(p, _)| {
+                !matches!(
+                    p,
+                    FuncParam::Env(_) | FuncParam::Regular(Type::UserDef(_), _)
+                )
+            })
+            .map(|(_, v)| v.clone())
+            .collect();
+        self.push_func(func, params, None)
+    }
+
+    // Periodically write pending paths to the given checkpoint file.
+    pub fn set_checkpoint(&mut self, file: String, interval: Duration) {
+        self.checkpoint = Some((file, interval));
+        self.last_checkpoint = Instant::now();
+    }
+
+    // Restore coverage and statistics of a previous exploration. Pending
+    // paths of the checkpoint must be passed to exec_symbolic separately.
+    pub fn restore(&mut self, checkpoint: &Checkpoint) -> Result<(), Error> {
+        for (func, label) in checkpoint.coverage.iter() {
+            let func = self
+                .state
+                .get_func(func)
+                .ok_or(Error::UnknownFunction(func.to_string()))?;
+            let block = func
+                .body
+                .iter()
+                .find(|b| b.label == *label)
+                .ok_or(Error::UnknownLabel(label.to_string()))?;
+            self.coverage
+                .insert((func.name.as_str(), block.label.as_str()));
+        }
+
+        self.paths = checkpoint.paths;
+        Ok(())
+    }
+
+    fn checkpoint_due(&self) -> bool {
+        match &self.checkpoint {
+            Some((_, interval)) => self.last_checkpoint.elapsed() >= *interval,
+            None => false,
+        }
+    }
+
+    fn write_checkpoint(
+        &mut self,
+        prefixes: &[Vec<bool>],
+        strategy: &dyn SearchStrategy<'ctx, 'src>,
+    ) -> Result<(), Error> {
+        let file = match &self.checkpoint {
+            Some((file, _)) => file,
+            None => return Ok(()),
+        };
+
+        // Pending prefixes are started after all paths of the strategy
+        // have been explored, hence they need to be stored first.
+        let mut pending = prefixes.to_vec();
+        pending.extend(strategy.pending().iter().map(|p| p.trace().to_vec()));
+
+        let coverage = self
+            .coverage
+            .iter()
+            .map(|(f, l)| (f.to_string(), l.to_string()))
             .collect();
-        self.exec_func(func, params)?;
+        let checkpoint = Checkpoint {
+            pending,
+            coverage,
+            paths: self.paths,
+        };
+        checkpoint.write(file).map_err(Error::CheckpointFailed)?;
 
+        self.last_checkpoint = Instant::now();
         Ok(())
     }
 
+    fn add_to_pool(&self, pool: &mut Vec<Path<'ctx, 'src>>, path: Path<'ctx, 'src>) {
+        let key = path.state.merge_key();
+        match pool.iter_mut().find(|p| p.state.merge_key() == key) {
+            Some(p) => p.merge(self.ctx, path),
+            None => pool.push(path),
+        }
+    }
+
+    // Re-executes the path described by the given branch decisions.
+    // Contrary to exec_symbolic, the replayed part is reported as well.
+    // If further conditional jumps are encountered after all decisions
+    // have been replayed, all continuations of the path are explored.
+    pub fn exec_replay(&mut self, name: &str, decisions: &[bool]) -> Result<(), Error> {
+        self.report_replay = true;
+        let mut strategy = DepthFirst::new();
+        self.exec_symbolic(name, vec![decisions.to_vec()], &mut strategy)
+    }
+
+    // Concolic execution of the function with the given name. The function
+    // is executed for concrete values of its parameters, starting with the
+    // given seed. Each execution follows a single path and the negation of
+    // its branch conditions generates new inputs (generational search).
+    pub fn exec_concolic(&mut self, name: &str, seed: Vec<u64>) -> Result<(), Error> {
+        let init = self.state.clone();
+        let mut seen: HashSet<Vec<bool>> = HashSet::new();
+
+        // Inputs are paired with the index of the first branch condition
+        // which may be negated. Conditions before this index have already
+        // been negated for the parent input.
+        let mut worklist = VecDeque::from([(seed, 0)]);
+        while let Some((input, bound)) = worklist.pop_front() {
+            let values: Vec<_> = input.iter().map(|v| v.to_string()).collect();
+            writeln!(
+                self.out,
+                "[concolic] Executing input ({})",
+                values.join(", ")
+            )
+            .unwrap();
+
+            self.restart(init.clone());
+            self.concrete = Some(input);
+            self.exec_symbolic(name, Vec::new(), &mut DepthFirst::new())?;
+
+            if !seen.insert(self.trace.clone()) {
+                continue;
+            }
+            for i in bound..self.constraints.len() {
+                if let Some(child) = self.negate_branch(i)? {
+                    worklist.push_back((child, i + 1));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Find an input which satisfies the first n constraints of
+    // the current path but not the nth constraint.
+    fn negate_branch(&self, n: usize) -> Result<Option<Vec<u64>>, Error> {
+        let solver = z3::Solver::new(self.ctx);
+        self.set_solver_params(&solver);
+        for c in self.constraints[..n].iter() {
+            solver.assert(c);
+        }
+        solver.assert(&self.constraints[n].not());
+
+        match solver.check() {
+            SatResult::Sat => {}
+            SatResult::Unsat => return Ok(None),
+            SatResult::Unknown => match self.opts.solver.unknown {
+                // Use the model found so far, if the solver provides one.
+                UnknownPolicy::Feasible => {}
+                UnknownPolicy::Infeasible => return Ok(None),
+                UnknownPolicy::Abort => return Err(Error::SolverUnknown),
+            },
+        }
+
+        let input = solver.get_model().and_then(|model| {
+            self.inputs
+                .iter()
+                .map(|i| model.eval(i, true)?.as_u64())
+                .collect()
+        });
+        Ok(input)
+    }
+
+    // Reports the termination of the current path. Errors which
+    // don't terminate an individual path are passed through.
+    fn end_path(&mut self, err: Error) -> Result<(), Error> {
+        self.paths += 1;
+        if !self.replay.is_empty() {
+            let n = self.replay.len();
+            writeln!(self.out, "Path ended with {} branch decisions left", n).unwrap();
+            self.replay.clear();
+        }
+
+        match err {
+            Error::HaltExecution => self.dump(),
+            Error::PathTruncated(reason) => {
+                writeln!(self.out, "Truncating path: {}", reason).unwrap();
+                self.dump_constraints();
+                self.dump();
+            }
+            Error::Fault(fault) => {
+                writeln!(self.out, "Aborting path: unavoidable {}", fault).unwrap();
+                self.dump_constraints();
+                self.dump();
+            }
+            Error::SolverUnknown => {
+                writeln!(self.out, "Aborting path: solver returned unknown").unwrap();
+                self.dump_constraints();
+                self.dump();
+            }
+            x => {
+                self.flush();
+                return Err(x);
+            }
+        }
+
+        self.flush();
+        Ok(())
+    }
+
+    // Print the output of the current path. The output is written at
+    // once to prevent interleaving with output of concurrent workers.
+    fn flush(&mut self) {
+        print!("{}", self.out);
+        self.out.clear();
+    }
+
+    fn dump_constraints(&mut self) {
+        writeln!(self.out, "Path constraints:").unwrap();
+        for c in self.constraints.iter() {
+            writeln!(self.out, "\t{}", c).unwrap();
+        }
+    }
+
     // XXX: Just a hack to see stuff right now.
-    pub fn dump(&self) {
-        self.solver.check();
+    pub fn dump(&mut self) {
+        // In concolic mode, report the input the path was executed for.
+        let result = match self.input_assumptions() {
+            Some(assumptions) => self.solver.check_assumptions(&assumptions),
+            None => self.solver.check(),
+        };
+        if self.opts.print_decisions {
+            let decisions = format_decisions(&self.trace);
+            writeln!(self.out, "Branch decisions: {}", decisions).unwrap();
+        }
 
-        println!("Local variables:");
-        self.state.dump_locals();
+        writeln!(self.out, "Local variables:").unwrap();
+        self.state.dump_locals(&mut self.out);
 
-        let model = self.solver.get_model();
+        let model = match result {
+            SatResult::Sat => self.solver.get_model(),
+            _ => None,
+        };
         match model {
-            None => panic!("Couldn't generate a Z3 model"),
+            None => {
+                let msg = format!("No model available (solver returned {:?})", result);
+                writeln!(self.out, "{}", msg).unwrap();
+            }
             Some(m) => {
                 let out = format!("{}", m);
-                println!("Symbolic variable values:");
-                println!("\t{}", out.replace("\n", "\n\t"));
+                writeln!(self.out, "Symbolic variable values:").unwrap();
+                writeln!(self.out, "\t{}", out.replace("\n", "\n\t")).unwrap();
             }
         };
     }
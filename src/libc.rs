@@ -0,0 +1,360 @@
+use z3::ast::{Ast, Bool, BV};
+
+use crate::error::*;
+use crate::interp::*;
+use crate::state::MAX_ALLOC;
+use crate::value::*;
+
+// Native model of a libc function. Models receive the arguments of the
+// call and return the return value of the function, if any. Functions
+// defined in the input always take precedence over these models.
+pub type Model = for<'ctx, 'src> fn(&mut Interp<'ctx, 'src>, &[BV<'ctx>]) -> ModelResult<'ctx>;
+pub type ModelResult<'ctx> = Result<Return<'ctx>, Error>;
+
+pub enum Return<'ctx> {
+    Value(Option<BV<'ctx>>),
+    // Fork execution, the call is executed again on both the
+    // path where the condition holds and the one where it doesn't.
+    Fork(Bool<'ctx>),
+}
+
+// Maximum amount of bytes examined by string functions. As memory is
+// unconstrained symbolic by default, strings may be arbitrarily long.
+const MAX_STRLEN: u64 = 4096;
+
+pub fn lookup(name: &str) -> Option<Model> {
+    match name {
+        "malloc" => Some(malloc),
+        "calloc" => Some(calloc),
+        "realloc" => Some(realloc),
+        "free" => Some(free),
+        "memcpy" => Some(memcpy),
+        "memset" => Some(memset),
+        "memcmp" => Some(memcmp),
+        "strlen" => Some(strlen),
+        "strcmp" => Some(strcmp),
+        "puts" => Some(puts),
+        "putchar" => Some(putchar),
+        "abort" => Some(abort),
+        "exit" => Some(exit),
+        _ => None,
+    }
+}
+
+fn get_args<'a, 'ctx, const N: usize>(args: &'a [BV<'ctx>]) -> Result<&'a [BV<'ctx>; N], Error> {
+    args.try_into().map_err(|_| Error::InvalidCall)
+}
+
+fn offset<'ctx>(addr: &BV<'ctx>, n: u64) -> BV<'ctx> {
+    addr.bvadd(&BV::from_u64(addr.get_ctx(), n, LONG_SIZE))
+}
+
+// Scans the given strings byte by byte. The given function returns
+// the result for the current index (if the scan stops there) and the
+// condition for continuing the scan. The scan ends once continuing is
+// infeasible under the path constraints. Accesses to each string are
+// checked under the condition that the scan reaches the current index.
+fn scan<'ctx, 'src, F>(
+    interp: &mut Interp<'ctx, 'src>,
+    strings: &[&BV<'ctx>],
+    mut step: F,
+) -> Result<BV<'ctx>, Error>
+where
+    F: FnMut(&mut Interp<'ctx, 'src>, u64) -> (BV<'ctx>, Bool<'ctx>),
+{
+    let ctx = interp.ctx();
+    let mut results = Vec::new();
+    let mut conds = Vec::new();
+    for i in 0..MAX_STRLEN {
+        let refs: Vec<_> = conds.iter().collect();
+        let reached = Bool::and(ctx, &refs);
+        for s in strings {
+            interp.check_access_if(&reached, &offset(s, i), 1)?;
+        }
+
+        let (result, cont) = step(interp, i);
+        results.push((result, cont.clone()));
+        conds.push(cont);
+
+        let refs: Vec<_> = conds.iter().collect();
+        if !interp.is_feasible(&Bool::and(ctx, &refs))? {
+            // The result for index i is used if the scan stops at i.
+            let mut value = results.pop().unwrap().0;
+            for (result, cont) in results.into_iter().rev() {
+                value = cont.ite(&value, &result);
+            }
+            return Ok(value);
+        }
+    }
+
+    Err(Error::PathTruncated(format!(
+        "string exceeds {} bytes",
+        MAX_STRLEN
+    )))
+}
+
+// Determines the length of a NUL-terminated string.
+fn string_length<'ctx>(interp: &mut Interp<'ctx, '_>, s: &BV<'ctx>) -> Result<BV<'ctx>, Error> {
+    let v = ValueFactory::new(interp.ctx());
+    scan(interp, &[s], |interp, i| {
+        let byte = interp.state().mem.load_byte(offset(s, i));
+        (v.make_long(i), byte._eq(&v.make_byte(0)).not())
+    })
+}
+
+// Reads a NUL-terminated string for output, symbolic bytes are
+// replaced by values satisfying the current path constraints.
+fn read_string<'ctx>(interp: &mut Interp<'ctx, '_>, addr: &BV<'ctx>) -> Result<String, Error> {
+    let mut string = String::new();
+    for i in 0..MAX_STRLEN {
+        let byte = interp.state().mem.load_byte(offset(addr, i));
+        match interp.eval(&byte)? {
+            0 => return Ok(string),
+            c => string.push(c as u8 as char),
+        }
+    }
+
+    Err(Error::PathTruncated(format!(
+        "string exceeds {} bytes",
+        MAX_STRLEN
+    )))
+}
+
+////
+// Memory allocation
+////
+
+// Applies the configured SizePolicy to the size of a heap allocation.
+// Sizes exceeding MAX_ALLOC cause the allocation to fail, i.e. null
+// is returned. Returns Err with the result of the call if the size
+// isn't used, that is, if the allocation fails or execution forks.
+fn alloc_size<'ctx>(
+    interp: &mut Interp<'ctx, '_>,
+    size: &BV<'ctx>,
+) -> Result<Result<BV<'ctx>, Return<'ctx>>, Error> {
+    let v = ValueFactory::new(interp.ctx());
+    let max = BV::from_u64(interp.ctx(), MAX_ALLOC, size.get_size());
+    let valid = size.bvule(&max);
+    if !interp.is_feasible(&valid)? {
+        return Ok(Err(Return::Value(Some(v.make_long(0)))));
+    } else if interp.is_feasible(&valid.not())? {
+        return Ok(Err(Return::Fork(valid)));
+    }
+
+    // Sizes may be wider than a long to account for overflows.
+    let size = if size.get_size() > LONG_SIZE {
+        size.extract(LONG_SIZE - 1, 0)
+    } else {
+        size.clone()
+    };
+
+    match interp.size_policy() {
+        SizePolicy::Symbolic => Ok(Ok(size)),
+        SizePolicy::Constrain => Ok(Ok(v.make_long(interp.concretize(&size)?))),
+        SizePolicy::Fork => {
+            let n = v.make_long(interp.eval(&size)?);
+            let cond = size._eq(&n);
+            if interp.is_feasible(&cond.not())? {
+                return Ok(Err(Return::Fork(cond)));
+            }
+            Ok(Ok(n))
+        }
+    }
+}
+
+// Reports frees of pointers which don't point to the start of a live
+// heap object. Freeing a null pointer is valid and has no effect.
+fn check_free<'ctx>(interp: &mut Interp<'ctx, '_>, ptr: &BV<'ctx>) -> Result<(), Error> {
+    let freed = interp.state().mem.heap_ptr(ptr, false);
+    interp.check_fault("double free", freed)?;
+
+    let v = ValueFactory::new(interp.ctx());
+    let null = ptr._eq(&v.make_long(0));
+    let live = interp.state().mem.heap_ptr(ptr, true);
+    let invalid = Bool::and(interp.ctx(), &[&null.not(), &live.not()]);
+    interp.check_fault("free of non-heap pointer", invalid)
+}
+
+fn malloc<'ctx>(interp: &mut Interp<'ctx, '_>, args: &[BV<'ctx>]) -> ModelResult<'ctx> {
+    let [size] = get_args::<1>(args)?;
+    let size = match alloc_size(interp, size)? {
+        Ok(size) => size,
+        Err(ret) => return Ok(ret),
+    };
+
+    Ok(Return::Value(Some(interp.state().heap_alloc(&size))))
+}
+
+fn calloc<'ctx>(interp: &mut Interp<'ctx, '_>, args: &[BV<'ctx>]) -> ModelResult<'ctx> {
+    let [nmemb, size] = get_args::<2>(args)?;
+    // The product is computed without overflow, allocations
+    // whose size overflows fail like any other large allocation.
+    let total = nmemb.zero_ext(LONG_SIZE).bvmul(&size.zero_ext(LONG_SIZE));
+    let total = match alloc_size(interp, &total)? {
+        Ok(total) => total,
+        Err(ret) => return Ok(ret),
+    };
+
+    let v = ValueFactory::new(interp.ctx());
+    let state = interp.state();
+    let addr = state.heap_alloc(&total);
+    state.mem.fill(&addr, &v.make_byte(0), &total);
+
+    Ok(Return::Value(Some(addr)))
+}
+
+fn realloc<'ctx>(interp: &mut Interp<'ctx, '_>, args: &[BV<'ctx>]) -> ModelResult<'ctx> {
+    let [ptr, size] = get_args::<2>(args)?;
+    let size = match alloc_size(interp, size)? {
+        Ok(size) => size,
+        Err(ret) => return Ok(ret),
+    };
+    check_free(interp, ptr)?;
+
+    // The size of the old object is zero for null pointers, in which
+    // case realloc behaves like malloc. Copying requires a concrete size.
+    let old_size = interp.state().mem.heap_size(ptr);
+    let n = interp.concretize(&old_size.bvult(&size).ite(&old_size, &size))?;
+
+    let state = interp.state();
+    let addr = state.heap_alloc(&size);
+    state.mem.copy(&addr, ptr, n);
+    state.mem.free(ptr);
+
+    Ok(Return::Value(Some(addr)))
+}
+
+fn free<'ctx>(interp: &mut Interp<'ctx, '_>, args: &[BV<'ctx>]) -> ModelResult<'ctx> {
+    let [ptr] = get_args::<1>(args)?;
+    check_free(interp, ptr)?;
+
+    interp.state().mem.free(ptr);
+    Ok(Return::Value(None))
+}
+
+////
+// Memory functions
+////
+
+fn memcpy<'ctx>(interp: &mut Interp<'ctx, '_>, args: &[BV<'ctx>]) -> ModelResult<'ctx> {
+    let [dest, src, n] = get_args::<3>(args)?;
+    let n = interp.concretize(n)?;
+    if n > 0 {
+        interp.check_access(src, n)?;
+        interp.check_access(dest, n)?;
+    }
+    interp.state().mem.copy(dest, src, n);
+
+    Ok(Return::Value(Some(dest.clone())))
+}
+
+fn memset<'ctx>(interp: &mut Interp<'ctx, '_>, args: &[BV<'ctx>]) -> ModelResult<'ctx> {
+    let [dest, c, n] = get_args::<3>(args)?;
+    let n = interp.concretize(n)?;
+
+    if n > 0 {
+        interp.check_access(dest, n)?;
+    }
+
+    let v = ValueFactory::new(interp.ctx());
+    let byte = c.extract(7, 0);
+    interp.state().mem.fill(dest, &byte, &v.make_long(n));
+
+    Ok(Return::Value(Some(dest.clone())))
+}
+
+fn memcmp<'ctx>(interp: &mut Interp<'ctx, '_>, args: &[BV<'ctx>]) -> ModelResult<'ctx> {
+    let [s1, s2, n] = get_args::<3>(args)?;
+    let n = interp.concretize(n)?;
+    if n > 0 {
+        interp.check_access(s1, n)?;
+        interp.check_access(s2, n)?;
+    }
+
+    // Compare from the last byte backwards, the first difference decides.
+    let v = ValueFactory::new(interp.ctx());
+    let mut result = v.make_word(0);
+    for i in (0..n).rev() {
+        let mem = &interp.state().mem;
+        let b1 = mem.load_byte(offset(s1, i));
+        let b2 = mem.load_byte(offset(s2, i));
+
+        let diff = b1.bvult(&b2).ite(&v.make_word(u32::MAX), &v.make_word(1));
+        result = b1._eq(&b2).ite(&result, &diff);
+    }
+
+    Ok(Return::Value(Some(result)))
+}
+
+////
+// String functions
+////
+
+fn strlen<'ctx>(interp: &mut Interp<'ctx, '_>, args: &[BV<'ctx>]) -> ModelResult<'ctx> {
+    let [s] = get_args::<1>(args)?;
+    let len = string_length(interp, s)?;
+    Ok(Return::Value(Some(len)))
+}
+
+fn strcmp<'ctx>(interp: &mut Interp<'ctx, '_>, args: &[BV<'ctx>]) -> ModelResult<'ctx> {
+    let [s1, s2] = get_args::<2>(args)?;
+    let v = ValueFactory::new(interp.ctx());
+
+    let result = scan(interp, &[s1, s2], |interp, i| {
+        let mem = &interp.state().mem;
+        let b1 = mem.load_byte(offset(s1, i));
+        let b2 = mem.load_byte(offset(s2, i));
+
+        let diff = b1.bvult(&b2).ite(&v.make_word(u32::MAX), &v.make_word(1));
+        let result = b1._eq(&b2).ite(&v.make_word(0), &diff);
+
+        // Continue while both strings are equal and not terminated.
+        let cont = Bool::and(
+            interp.ctx(),
+            &[&b1._eq(&b2), &b1._eq(&v.make_byte(0)).not()],
+        );
+        (result, cont)
+    })?;
+    Ok(Return::Value(Some(result)))
+}
+
+////
+// Output
+////
+
+fn puts<'ctx>(interp: &mut Interp<'ctx, '_>, args: &[BV<'ctx>]) -> ModelResult<'ctx> {
+    let [s] = get_args::<1>(args)?;
+    string_length(interp, s)?;
+    let string = read_string(interp, s)?;
+    interp.print(&format!("[puts] {:?}", string));
+
+    let v = ValueFactory::new(interp.ctx());
+    Ok(Return::Value(Some(v.make_word(0))))
+}
+
+fn putchar<'ctx>(interp: &mut Interp<'ctx, '_>, args: &[BV<'ctx>]) -> ModelResult<'ctx> {
+    let [c] = get_args::<1>(args)?;
+    let byte = interp.eval(&c.extract(7, 0))? as u8;
+    interp.print(&format!("[putchar] {:?}", byte as char));
+
+    Ok(Return::Value(Some(c.clone())))
+}
+
+////
+// Program termination
+////
+
+fn abort<'ctx>(interp: &mut Interp<'ctx, '_>, args: &[BV<'ctx>]) -> ModelResult<'ctx> {
+    get_args::<0>(args)?;
+    interp.print("[abort] Program aborted");
+    Err(Error::HaltExecution)
+}
+
+fn exit<'ctx>(interp: &mut Interp<'ctx, '_>, args: &[BV<'ctx>]) -> ModelResult<'ctx> {
+    let [status] = get_args::<1>(args)?;
+    interp.print(&format!(
+        "[exit] Program exited with status {}",
+        status.simplify()
+    ));
+    Err(Error::HaltExecution)
+}
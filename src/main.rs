@@ -1,35 +1,239 @@
+mod checkpoint;
 mod error;
 mod interp;
+mod libc;
 mod memory;
+mod parallel;
+mod search;
 mod state;
 mod value;
 
 use qbe_reader as qbe;
 use std::env;
+use std::io;
+use std::process::exit;
+use std::str::FromStr;
+use std::time::Duration;
 use z3::{Config, Context};
 
+use checkpoint::Checkpoint;
 use interp::*;
 
-fn run_qbe(fname: &str, source: Vec<qbe::Definition>) {
+struct Args {
+    jobs: usize,
+    search: String,
+    seed: u64,
+    decisions: Option<Vec<bool>>,
+    concolic: Option<Vec<u64>>,
+    input: Option<Vec<u64>>,
+    checkpoint: Option<String>,
+    interval: u64,
+    resume: bool,
+    interp: Options,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            jobs: 1,
+            search: "dfs".to_string(),
+            seed: 0,
+            decisions: None,
+            concolic: None,
+            input: None,
+            checkpoint: None,
+            interval: 60,
+            resume: false,
+            interp: Options::default(),
+        }
+    }
+}
+
+fn usage(prog: &str) -> ! {
+    eprintln!("Usage: {} [OPTIONS] FILE FUNC", prog);
+    eprintln!(
+        "       {} replay [OPTIONS] FILE FUNC --path DECISIONS",
+        prog
+    );
+    eprintln!(
+        "Options: [--jobs N] [--merge] [--search dfs|bfs|random|coverage] [--seed N] \
+         [--max-visits N] [--max-branches N] [--max-insts N] [--timeout MS] \
+         [--unknown feasible|infeasible|abort] [--alloc-size symbolic|constrain|fork] \
+         [--print-decisions] \
+         [--concolic [--input V1,V2,...]] \
+         [--checkpoint FILE [--checkpoint-interval SECS] [--resume]]"
+    );
+    exit(1);
+}
+
+fn parse_arg<T: FromStr>(prog: &str, arg: Option<String>) -> T {
+    let arg = arg.unwrap_or_else(|| usage(prog));
+    arg.parse().unwrap_or_else(|_| usage(prog))
+}
+
+// Parse branch decisions, as reported by --print-decisions.
+fn parse_decisions(prog: &str, arg: Option<String>) -> Vec<bool> {
+    let arg = arg.unwrap_or_else(|| usage(prog));
+    checkpoint::parse_decisions(&arg).unwrap_or_else(|| usage(prog))
+}
+
+// Parse comma-separated seed values for concolic execution.
+fn parse_input(prog: &str, arg: Option<String>) -> Vec<u64> {
+    let arg = arg.unwrap_or_else(|| usage(prog));
+    arg.split(',')
+        .map(|v| {
+            // Allow negative values, these are stored in two's complement.
+            let v: i64 = v.trim().parse().unwrap_or_else(|_| usage(prog));
+            v as u64
+        })
+        .collect()
+}
+
+// Describes the first unsupported combination of options, if any.
+fn conflicting_options(args: &Args) -> Option<&'static str> {
+    let jobs = args.jobs > 1;
+    let merge = args.interp.merge;
+    let replay = args.decisions.is_some();
+    let concolic = args.concolic.is_some();
+    let checkpoint = args.checkpoint.is_some();
+
+    if replay && (jobs || concolic || checkpoint) {
+        Some("Replaying is not supported with multiple jobs, concolic execution or checkpoints")
+    } else if merge && (jobs || replay || args.interp.print_decisions) {
+        // The decisions of a merged path only describe one of the merged paths.
+        Some("State merging is not supported with multiple jobs, replaying or printing decisions")
+    } else if concolic && (jobs || merge) {
+        Some("Concolic execution is not supported with multiple jobs or merging")
+    } else if checkpoint && (jobs || merge || concolic) {
+        Some("Checkpoints are not supported with multiple jobs, merging or concolic execution")
+    } else {
+        None
+    }
+}
+
+fn run_qbe(args: &Args, fname: &str, source: Vec<qbe::Definition>) {
+    if search::from_name(&args.search, args.seed).is_none() {
+        eprintln!("Unknown search strategy '{}'", args.search);
+        exit(1);
+    }
+
+    if let Some(conflict) = conflicting_options(args) {
+        eprintln!("{}", conflict);
+        exit(1);
+    }
+
+    if args.jobs > 1 {
+        parallel::exec_parallel(
+            args.jobs,
+            &source,
+            fname,
+            &args.search,
+            args.seed,
+            args.interp,
+        )
+        .unwrap();
+        return;
+    }
+
     let mut cfg = Config::new();
     cfg.set_model_generation(true);
     let ctx = Context::new(&cfg);
 
-    let mut interp = Interp::new(&ctx, &source).unwrap();
-    interp.exec_symbolic(&fname.to_string()).unwrap();
+    let mut interp = Interp::new(&ctx, &source, args.interp).unwrap();
+    match (&args.decisions, &args.concolic) {
+        (Some(decisions), _) => interp.exec_replay(fname, decisions).unwrap(),
+        (None, Some(seed)) => interp.exec_concolic(fname, seed.clone()).unwrap(),
+        (None, None) => {
+            let mut prefixes = Vec::new();
+            if let Some(file) = &args.checkpoint {
+                if args.resume {
+                    match Checkpoint::read(file) {
+                        Ok(checkpoint) if checkpoint.pending.is_empty() => {
+                            println!("Exploration already completed");
+                            return;
+                        }
+                        Ok(checkpoint) => {
+                            interp.restore(&checkpoint).unwrap();
+                            prefixes = checkpoint.pending;
+                        }
+                        // Without a checkpoint, the exploration starts from scratch.
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                        Err(e) => {
+                            eprintln!("Failed to read checkpoint '{}': {}", file, e);
+                            exit(1);
+                        }
+                    }
+                }
+                interp.set_checkpoint(file.clone(), Duration::from_secs(args.interval));
+            }
+
+            let mut strategy = search::from_name(&args.search, args.seed).unwrap();
+            interp
+                .exec_symbolic(fname, prefixes, strategy.as_mut())
+                .unwrap();
+        }
+    }
 }
 
 fn main() {
-    let mut args = env::args();
-    let prog = args.next().unwrap();
+    let mut argv = env::args();
+    let prog = argv.next().unwrap();
 
-    if args.len() <= 1 {
-        eprintln!("Usage: {} FILE FUNC", prog);
-    } else {
-        let path = args.next().unwrap();
-        let func = args.next().unwrap();
+    let mut args = Args::default();
+    let mut positional = Vec::new();
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "--jobs" => args.jobs = parse_arg(&prog, argv.next()),
+            "--merge" => args.interp.merge = true,
+            "--search" => args.search = argv.next().unwrap_or_else(|| usage(&prog)),
+            "--seed" => args.seed = parse_arg(&prog, argv.next()),
+            "--max-visits" => args.interp.limits.max_visits = Some(parse_arg(&prog, argv.next())),
+            "--max-branches" => {
+                args.interp.limits.max_branches = Some(parse_arg(&prog, argv.next()))
+            }
+            "--max-insts" => args.interp.limits.max_insts = Some(parse_arg(&prog, argv.next())),
+            "--timeout" => args.interp.solver.timeout = Some(parse_arg(&prog, argv.next())),
+            "--unknown" => args.interp.solver.unknown = parse_arg(&prog, argv.next()),
+            "--alloc-size" => args.interp.alloc_size = parse_arg(&prog, argv.next()),
+            "--print-decisions" => args.interp.print_decisions = true,
+            "--concolic" => args.concolic = Some(Vec::new()),
+            "--input" => args.input = Some(parse_input(&prog, argv.next())),
+            "--path" => args.decisions = Some(parse_decisions(&prog, argv.next())),
+            "--checkpoint" => args.checkpoint = Some(argv.next().unwrap_or_else(|| usage(&prog))),
+            "--checkpoint-interval" => args.interval = parse_arg(&prog, argv.next()),
+            "--resume" => args.resume = true,
+            _ => positional.push(arg),
+        }
+    }
+
+    // The replay subcommand requires a path, which is not supported otherwise.
+    if positional.first().map(|s| s.as_str()) == Some("replay") {
+        if args.decisions.is_none() {
+            usage(&prog);
+        }
+        positional.remove(0);
+    } else if args.decisions.is_some() {
+        usage(&prog);
+    }
+
+    // Seed values are only used for concolic execution.
+    if args.input.is_some() && args.concolic.is_none() {
+        usage(&prog);
+    } else if let Some(seed) = args.input.take() {
+        args.concolic = Some(seed);
+    }
+
+    // Resuming requires a checkpoint to resume from.
+    if args.resume && args.checkpoint.is_none() {
+        usage(&prog);
+    }
 
-        let defs = qbe::parse_file(path).unwrap();
-        run_qbe(&func, defs);
+    if positional.len() != 2 {
+        usage(&prog);
     }
+    let path = &positional[0];
+    let func = &positional[1];
+
+    let defs = qbe::parse_file(path).unwrap();
+    run_qbe(&args, func, defs);
 }
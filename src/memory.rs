@@ -1,11 +1,85 @@
+use std::rc::Rc;
+
 use z3::{
-    ast::{Array, BV},
+    ast::{Array, Ast, Bool, BV},
     Context, Sort,
 };
+use z3_sys::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ObjectKind {
+    Data,
+    Stack,
+    Heap,
+    Env,
+}
+
+// Region of memory which has been allocated as a whole, e.g. a data
+// definition or a stack slot created by an alloc instruction.
+#[derive(Clone)]
+pub struct Object<'ctx> {
+    pub base: BV<'ctx>,
+    pub size: BV<'ctx>,
+    pub kind: ObjectKind,
+
+    // Condition under which the object hasn't been freed yet.
+    pub alive: Bool<'ctx>,
 
+    // Whether each byte has been initialized, indexed by offset.
+    // Only tracked for stack and heap objects, data is initialized.
+    init: Option<Array<'ctx>>,
+}
+
+impl<'ctx> Object<'ctx> {
+    // Whether n bytes starting at the given address are within this object.
+    pub fn contains(&self, addr: &BV<'ctx>, n: u64) -> Bool<'ctx> {
+        let ctx = addr.get_ctx();
+        let off = addr.bvsub(&self.base);
+        let n = BV::from_u64(ctx, n, 64);
+
+        // Written as n <= size - off to avoid overflows of off + n.
+        Bool::and(
+            ctx,
+            &[
+                &addr.bvuge(&self.base),
+                &off.bvule(&self.size),
+                &n.bvule(&self.size.bvsub(&off)),
+            ],
+        )
+    }
+}
+
+// Array mapping each 64-bit index to the result of the given function,
+// i.e. a lambda term. Used to update an unbounded range of an array.
+fn lambda<'ctx, A, F>(ctx: &'ctx Context, f: F) -> Array<'ctx>
+where
+    A: Ast<'ctx>,
+    F: Fn(&BV<'ctx>) -> A,
+{
+    let idx = BV::new_const(ctx, "idx", 64);
+    let body = f(&idx);
+
+    let z3_ctx = ctx.get_z3_context();
+    unsafe {
+        let bound = [Z3_to_app(z3_ctx, idx.get_z3_ast())];
+        let ast = Z3_mk_lambda_const(z3_ctx, 1, bound.as_ptr(), body.get_z3_ast());
+        Array::wrap(ctx, ast)
+    }
+}
+
+// Maximum amount of released objects which are kept, see Memory::retire.
+const MAX_RELEASED: usize = 64;
+
+#[derive(Clone)]
 pub struct Memory<'ctx> {
     ctx: &'ctx Context,
     pub data: Array<'ctx>,
+
+    // Objects are shared between clones and only copied when modified.
+    objects: Rc<Vec<Object<'ctx>>>,
+
+    // Objects which have been released on all paths, oldest first.
+    released: Rc<Vec<Object<'ctx>>>,
 }
 
 impl<'ctx> Memory<'ctx> {
@@ -20,12 +94,248 @@ impl<'ctx> Memory<'ctx> {
         Memory {
             ctx: ctx,
             data: ary,
+            objects: Rc::new(Vec::new()),
+            released: Rc::new(Vec::new()),
+        }
+    }
+
+    // Merge memory of two execution paths, see State::merge.
+    pub fn merge(&mut self, other: &Memory<'ctx>, guard: &Bool<'ctx>) {
+        self.data = guard.ite(&self.data, &other.data);
+
+        // XXX: Objects only allocated on one of the paths are
+        // considered allocated on both paths after the merge.
+        let mut objects: Vec<_> = self
+            .released
+            .iter()
+            .chain(self.objects.iter())
+            .cloned()
+            .collect();
+        for obj in other.released.iter().chain(other.objects.iter()) {
+            let known = objects
+                .iter_mut()
+                .find(|o| o.base == obj.base && o.size == obj.size);
+            match known {
+                Some(o) => {
+                    if o.alive != obj.alive {
+                        o.alive = guard.ite(&o.alive, &obj.alive);
+                    }
+                    if let (Some(init), Some(other_init)) = (&o.init, &obj.init) {
+                        if init != other_init {
+                            o.init = Some(guard.ite(init, other_init));
+                        }
+                    }
+                }
+                None => objects.push(obj.clone()),
+            }
+        }
+
+        // Objects released on both paths are released after the merge.
+        self.objects = Rc::new(objects);
+        self.released = Rc::new(Vec::new());
+        self.retire();
+    }
+
+    pub fn add_object(&mut self, base: BV<'ctx>, size: BV<'ctx>, kind: ObjectKind) {
+        assert!(base.get_size() == 64 && size.get_size() == 64);
+        let alive = Bool::from_bool(self.ctx, true);
+        let init = match kind {
+            ObjectKind::Stack | ObjectKind::Heap => Some(Array::const_array(
+                self.ctx,
+                &Sort::bitvector(self.ctx, 64),
+                &Bool::from_bool(self.ctx, false),
+            )),
+            _ => None,
+        };
+
+        let obj = Object {
+            base,
+            size,
+            kind,
+            alive,
+            init,
+        };
+        Rc::make_mut(&mut self.objects).push(obj);
+    }
+
+    // Moves objects which have been released on all paths, i.e. whose
+    // alive condition is false, to the list of released objects. Only the
+    // most recently released objects are kept, such that the cost of checks
+    // doesn't grow with the amount of released objects. Accesses to older
+    // objects are reported as out-of-bounds accesses instead.
+    fn retire(&mut self) {
+        let dead = |o: &Object<'ctx>| o.alive.as_bool() == Some(false);
+        if !self.objects.iter().any(dead) {
+            return;
+        }
+
+        let (dead, live): (Vec<_>, Vec<_>) = self.objects.iter().cloned().partition(dead);
+        self.objects = Rc::new(live);
+
+        let released = Rc::make_mut(&mut self.released);
+        released.extend(dead);
+        let excess = released.len().saturating_sub(MAX_RELEASED);
+        released.drain(..excess);
+    }
+
+    fn any<F>(&self, f: F) -> Bool<'ctx>
+    where
+        F: Fn(&Object<'ctx>) -> Option<Bool<'ctx>>,
+    {
+        let conds: Vec<_> = self.objects.iter().filter_map(f).collect();
+        let refs: Vec<_> = conds.iter().collect();
+        Bool::or(self.ctx, &refs)
+    }
+
+    // Like any, but also considers released objects.
+    fn any_released<F>(&self, f: F) -> Bool<'ctx>
+    where
+        F: Fn(&Object<'ctx>) -> Option<Bool<'ctx>>,
+    {
+        let objects = self.objects.iter().chain(self.released.iter());
+        let conds: Vec<_> = objects.filter_map(f).collect();
+        let refs: Vec<_> = conds.iter().collect();
+        Bool::or(self.ctx, &refs)
+    }
+
+    // Condition under which n bytes starting at the given address
+    // are entirely contained in a single object which is alive.
+    pub fn in_bounds(&self, addr: &BV<'ctx>, n: u64) -> Bool<'ctx> {
+        self.any(|o| Some(Bool::and(self.ctx, &[&o.contains(addr, n), &o.alive])))
+    }
+
+    // Like in_bounds, but only considers objects of the given kind.
+    pub fn in_object(&self, addr: &BV<'ctx>, n: u64, kind: ObjectKind) -> Bool<'ctx> {
+        self.any(|o| {
+            if o.kind != kind {
+                return None;
+            }
+            Some(Bool::and(self.ctx, &[&o.contains(addr, n), &o.alive]))
+        })
+    }
+
+    // Condition under which any of the n bytes starting at the given
+    // address is contained in an object of the given kind which is no
+    // longer alive, i.e. a freed heap object or a popped stack object.
+    pub fn in_dead(&self, addr: &BV<'ctx>, n: u64, kind: ObjectKind) -> Bool<'ctx> {
+        let last = addr.bvadd(&BV::from_u64(self.ctx, n - 1, 64));
+        self.any_released(|o| {
+            if o.kind != kind {
+                return None;
+            }
+            let overlaps = Bool::or(self.ctx, &[&o.contains(addr, 1), &o.contains(&last, 1)]);
+            Some(Bool::and(self.ctx, &[&overlaps, &o.alive.not()]))
+        })
+    }
+
+    // Marks all stack objects starting at or above the given address
+    // as no longer alive, used when a stack frame is popped.
+    pub fn pop_stack(&mut self, from: &BV<'ctx>) {
+        let objects = Rc::make_mut(&mut self.objects);
+        for o in objects.iter_mut().filter(|o| o.kind == ObjectKind::Stack) {
+            let alive = Bool::and(self.ctx, &[&o.alive, &o.base.bvult(from)]);
+            o.alive = alive.simplify();
+        }
+        self.retire();
+    }
+
+    /////
+    // Heap objects
+    /////
+
+    // Condition under which the pointer points to the start of a heap
+    // object which is alive or, if alive is false, has been freed.
+    pub fn heap_ptr(&self, ptr: &BV<'ctx>, alive: bool) -> Bool<'ctx> {
+        self.any_released(|o| {
+            if o.kind != ObjectKind::Heap {
+                return None;
+            }
+            let state = if alive {
+                o.alive.clone()
+            } else {
+                o.alive.not()
+            };
+            Some(Bool::and(self.ctx, &[&ptr._eq(&o.base), &state]))
+        })
+    }
+
+    // Size of the live heap object the pointer points to, zero if the
+    // pointer doesn't point to the start of such an object.
+    pub fn heap_size(&self, ptr: &BV<'ctx>) -> BV<'ctx> {
+        let mut size = BV::from_u64(self.ctx, 0, 64);
+        for o in self.objects.iter().filter(|o| o.kind == ObjectKind::Heap) {
+            let cond = Bool::and(self.ctx, &[&ptr._eq(&o.base), &o.alive]);
+            size = cond.ite(&o.size, &size);
+        }
+        size
+    }
+
+    // Marks the heap object the pointer points to as freed.
+    pub fn free(&mut self, ptr: &BV<'ctx>) {
+        let objects = Rc::make_mut(&mut self.objects);
+        for o in objects.iter_mut().filter(|o| o.kind == ObjectKind::Heap) {
+            let alive = Bool::and(self.ctx, &[&o.alive, &ptr._eq(&o.base).not()]);
+            o.alive = alive.simplify();
+        }
+        self.retire();
+    }
+
+    /////
+    // Initialization
+    /////
+
+    // Condition under which any of the n bytes starting at the
+    // given address is contained in an object without being
+    // initialized. Bytes outside of all objects are initialized.
+    pub fn uninit(&self, addr: &BV<'ctx>, n: u64) -> Bool<'ctx> {
+        let mut conds = Vec::new();
+        for i in 0..n {
+            let addr = addr.bvadd(&BV::from_u64(self.ctx, i, 64));
+            for o in self.objects.iter() {
+                let init = match &o.init {
+                    Some(init) => init.select(&addr.bvsub(&o.base)).as_bool().unwrap(),
+                    None => continue,
+                };
+
+                let inside = Bool::and(self.ctx, &[&o.contains(&addr, 1), &o.alive]);
+                let cond = Bool::and(self.ctx, &[&inside, &init.not()]).simplify();
+                if cond.as_bool() != Some(false) {
+                    conds.push(cond);
+                }
+            }
+        }
+
+        let refs: Vec<_> = conds.iter().collect();
+        Bool::or(self.ctx, &refs)
+    }
+
+    // Marks n bytes starting at the given address as initialized
+    // if the given condition holds and as uninitialized otherwise.
+    pub fn set_init(&mut self, addr: &BV<'ctx>, n: u64, init: &Bool<'ctx>) {
+        for i in 0..n {
+            let addr = addr.bvadd(&BV::from_u64(self.ctx, i, 64));
+            for idx in 0..self.objects.len() {
+                let o = &self.objects[idx];
+                let shadow = match &o.init {
+                    Some(shadow) if o.alive.as_bool() != Some(false) => shadow,
+                    _ => continue,
+                };
+
+                let inside = o.contains(&addr, 1).simplify();
+                let updated = match inside.as_bool() {
+                    Some(false) => continue,
+                    Some(true) => shadow.store(&addr.bvsub(&o.base), init),
+                    None => inside.ite(&shadow.store(&addr.bvsub(&o.base), init), shadow),
+                };
+                Rc::make_mut(&mut self.objects)[idx].init = Some(updated);
+            }
         }
     }
 
     pub fn store_byte(&mut self, addr: BV<'ctx>, value: BV<'ctx>) {
         assert!(addr.get_size() == 64);
         assert!(value.get_size() == 8);
+        self.set_init(&addr, 1, &Bool::from_bool(self.ctx, true));
         self.data = self.data.store(&addr, &value);
     }
 
@@ -61,6 +371,61 @@ impl<'ctx> Memory<'ctx> {
         bytes.reduce(|acc, e| acc.concat(&e)).unwrap()
     }
 
+    // Copies n bytes, including whether each byte is initialized. All
+    // bytes are loaded before storing them, as both (possibly symbolic)
+    // memory regions could overlap.
+    pub fn copy(&mut self, dst: &BV<'ctx>, src: &BV<'ctx>, n: u64) {
+        if n == 0 {
+            return;
+        }
+
+        let uninit: Vec<_> = (0..n)
+            .map(|i| self.uninit(&src.bvadd(&BV::from_u64(self.ctx, i, 64)), 1))
+            .collect();
+
+        let value = self.load_bitvector(src.clone(), n);
+        self.store_bitvector(dst.clone(), value);
+        for (i, cond) in uninit.into_iter().enumerate() {
+            let addr = dst.bvadd(&BV::from_u64(self.ctx, i as u64, 64));
+            self.set_init(&addr, 1, &cond.not());
+        }
+    }
+
+    // Sets n bytes starting at the given address to the given byte
+    // and marks them as initialized. Contrary to storing each byte
+    // separately, the amount of bytes may be large or even symbolic.
+    pub fn fill(&mut self, addr: &BV<'ctx>, byte: &BV<'ctx>, n: &BV<'ctx>) {
+        assert!(byte.get_size() == 8 && n.get_size() == 64);
+        let inside = |a: &BV<'ctx>| a.bvsub(addr).bvult(n);
+
+        let data = self.data.clone();
+        self.data = lambda(self.ctx, |i| {
+            inside(i).ite(byte, &data.select(i).as_bv().unwrap())
+        });
+
+        let initialized = Bool::from_bool(self.ctx, true);
+        for idx in 0..self.objects.len() {
+            let o = &self.objects[idx];
+            let shadow = match &o.init {
+                Some(shadow) if o.alive.as_bool() != Some(false) => shadow.clone(),
+                _ => continue,
+            };
+
+            // Skip objects which surely don't overlap with the filled bytes.
+            let overlaps = Bool::or(self.ctx, &[&o.contains(addr, 1), &inside(&o.base)]);
+            if overlaps.simplify().as_bool() == Some(false) {
+                continue;
+            }
+
+            let base = o.base.clone();
+            let updated = lambda(self.ctx, |off| {
+                let init = shadow.select(off).as_bool().unwrap();
+                inside(&base.bvadd(off)).ite(&initialized, &init)
+            });
+            Rc::make_mut(&mut self.objects)[idx].init = Some(updated);
+        }
+    }
+
     pub fn store_string(&mut self, addr: BV<'ctx>, str: &str) -> BV<'ctx> {
         let mut cur_addr = addr;
         for c in str.chars() {
@@ -153,4 +518,170 @@ mod tests {
         solver.assert(&loaded_word._eq(&word));
         assert_eq!(SatResult::Sat, solver.check());
     }
+
+    #[test]
+    fn test_bounds() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let mut mem = Memory::new(&ctx);
+
+        let base = BV::from_u64(&ctx, 0x1000, 64);
+        let size = BV::from_u64(&ctx, 8, 64);
+        mem.add_object(base, size, ObjectKind::Stack);
+
+        let addr = |n| BV::from_u64(&ctx, n, 64);
+        assert_eq!(
+            Some(true),
+            mem.in_bounds(&addr(0x1000), 8).simplify().as_bool()
+        );
+        assert_eq!(
+            Some(true),
+            mem.in_bounds(&addr(0x1004), 4).simplify().as_bool()
+        );
+        assert_eq!(
+            Some(false),
+            mem.in_bounds(&addr(0x1006), 4).simplify().as_bool()
+        );
+        assert_eq!(
+            Some(false),
+            mem.in_bounds(&addr(0xfff), 1).simplify().as_bool()
+        );
+        assert_eq!(
+            Some(false),
+            mem.in_bounds(&addr(u64::MAX), 2).simplify().as_bool()
+        );
+    }
+
+    #[test]
+    fn test_free() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let mut mem = Memory::new(&ctx);
+
+        let base = BV::from_u64(&ctx, 0x1000, 64);
+        let size = BV::from_u64(&ctx, 8, 64);
+        mem.add_object(base.clone(), size, ObjectKind::Heap);
+        assert_eq!(Some(true), mem.heap_ptr(&base, true).simplify().as_bool());
+
+        mem.free(&base);
+        assert_eq!(Some(false), mem.in_bounds(&base, 1).simplify().as_bool());
+        assert_eq!(
+            Some(true),
+            mem.in_dead(&base, 1, ObjectKind::Heap).simplify().as_bool()
+        );
+        assert_eq!(Some(true), mem.heap_ptr(&base, false).simplify().as_bool());
+    }
+
+    #[test]
+    fn test_init() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let mut mem = Memory::new(&ctx);
+
+        let base = BV::from_u64(&ctx, 0x1000, 64);
+        let size = BV::from_u64(&ctx, 4, 64);
+        mem.add_object(base.clone(), size, ObjectKind::Stack);
+        assert_eq!(Some(true), mem.uninit(&base, 1).simplify().as_bool());
+
+        mem.store_byte(base.clone(), BV::from_u64(&ctx, 0x42, 8));
+        assert_eq!(Some(false), mem.uninit(&base, 1).simplify().as_bool());
+        assert_eq!(Some(true), mem.uninit(&base, 2).simplify().as_bool());
+
+        // Bytes outside of all objects are considered initialized.
+        let addr = BV::from_u64(&ctx, 0x2000, 64);
+        assert_eq!(Some(false), mem.uninit(&addr, 1).simplify().as_bool());
+    }
+
+    #[test]
+    fn test_copy() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let mut mem = Memory::new(&ctx);
+
+        let size = BV::from_u64(&ctx, 2, 64);
+        let src = BV::from_u64(&ctx, 0x1000, 64);
+        let dst = BV::from_u64(&ctx, 0x1010, 64);
+        mem.add_object(src.clone(), size.clone(), ObjectKind::Heap);
+        mem.add_object(dst.clone(), size, ObjectKind::Heap);
+
+        mem.store_byte(src.clone(), BV::from_u64(&ctx, 0x42, 8));
+        mem.copy(&dst, &src, 2);
+        assert_eq!(Some(0x42), mem.load_byte(dst.clone()).simplify().as_u64());
+
+        // Only the first byte has been initialized in the source.
+        let second = dst.bvadd(&BV::from_u64(&ctx, 1, 64));
+        assert_eq!(Some(false), mem.uninit(&dst, 1).simplify().as_bool());
+        assert_eq!(Some(true), mem.uninit(&second, 1).simplify().as_bool());
+    }
+
+    #[test]
+    fn test_fill() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let mut mem = Memory::new(&ctx);
+
+        let addr = BV::from_u64(&ctx, 0x1000, 64);
+        let size = BV::new_const(&ctx, "size", 64);
+        mem.add_object(addr.clone(), size.clone(), ObjectKind::Heap);
+        mem.fill(&addr, &BV::from_u64(&ctx, 0, 8), &size);
+
+        // Bytes below the symbolic size are zeroed and initialized.
+        let byte = addr.bvadd(&BV::from_u64(&ctx, 0x100, 64));
+        let solver = Solver::new(&ctx);
+        solver.assert(&size.bvugt(&BV::from_u64(&ctx, 0x100, 64)));
+        let zeroed = mem.load_byte(byte.clone())._eq(&BV::from_u64(&ctx, 0, 8));
+        let init = mem.uninit(&byte, 1).not();
+        solver.assert(&Bool::and(&ctx, &[&zeroed, &init]).not());
+        assert_eq!(SatResult::Unsat, solver.check());
+
+        // Bytes outside of the filled range remain unchanged.
+        let outside = BV::from_u64(&ctx, 0xfff, 64);
+        let before = Memory::new(&ctx).load_byte(outside.clone());
+        let solver = Solver::new(&ctx);
+        solver.assert(&mem.load_byte(outside)._eq(&before).not());
+        assert_eq!(SatResult::Unsat, solver.check());
+    }
+
+    #[test]
+    fn test_released() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let mut mem = Memory::new(&ctx);
+
+        let size = BV::from_u64(&ctx, 16, 64);
+        let bases: Vec<_> = (0..=MAX_RELEASED as u64)
+            .map(|i| BV::from_u64(&ctx, 0x1000 + i * 16, 64))
+            .collect();
+        for base in bases.iter() {
+            mem.add_object(base.clone(), size.clone(), ObjectKind::Heap);
+            mem.free(base);
+        }
+
+        // Only the most recently released objects are kept.
+        let first = mem.in_dead(&bases[0], 1, ObjectKind::Heap);
+        assert_eq!(Some(false), first.simplify().as_bool());
+        let last = mem.in_dead(bases.last().unwrap(), 1, ObjectKind::Heap);
+        assert_eq!(Some(true), last.simplify().as_bool());
+        let valid = mem.in_bounds(&bases[1], 1);
+        assert_eq!(Some(false), valid.simplify().as_bool());
+    }
+
+    #[test]
+    fn test_pop_stack() {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let mut mem = Memory::new(&ctx);
+
+        let size = BV::from_u64(&ctx, 8, 64);
+        let caller = BV::from_u64(&ctx, 0x1000, 64);
+        let callee = BV::from_u64(&ctx, 0x1010, 64);
+        mem.add_object(caller.clone(), size.clone(), ObjectKind::Stack);
+        mem.add_object(callee.clone(), size, ObjectKind::Stack);
+
+        mem.pop_stack(&BV::from_u64(&ctx, 0x1008, 64));
+        assert_eq!(Some(true), mem.in_bounds(&caller, 8).simplify().as_bool());
+        assert_eq!(Some(false), mem.in_bounds(&callee, 8).simplify().as_bool());
+        let dead = mem.in_dead(&callee, 8, ObjectKind::Stack);
+        assert_eq!(Some(true), dead.simplify().as_bool());
+    }
 }
@@ -0,0 +1,160 @@
+use qbe_reader::Definition;
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+use z3::{Config, Context};
+
+use crate::error::*;
+use crate::interp::*;
+use crate::search::*;
+
+// Z3 terms can't be shared between threads. Hence, pending paths are
+// exchanged between workers as branch decision prefixes. The receiving
+// worker re-derives the state of the path, in its own Z3 context, by
+// replaying these decisions. As merged paths can't be described by a
+// single prefix, state merging is not supported in parallel mode.
+type Prefix = Vec<bool>;
+
+struct Queue {
+    pending: VecDeque<Prefix>,
+    idle: usize,
+    done: bool,
+}
+
+struct WorkQueue {
+    jobs: usize,
+    queue: Mutex<Queue>,
+    cond: Condvar,
+}
+
+impl WorkQueue {
+    fn new(jobs: usize) -> WorkQueue {
+        WorkQueue {
+            jobs,
+            queue: Mutex::new(Queue {
+                pending: VecDeque::from([Vec::new()]),
+                idle: 0,
+                done: false,
+            }),
+            cond: Condvar::new(),
+        }
+    }
+
+    // Blocks until a prefix is available. Returns None once all
+    // workers are idle and no prefixes are pending, i.e. once the
+    // exploration is complete.
+    fn take(&self) -> Option<Prefix> {
+        let mut queue = self.queue.lock().unwrap();
+        queue.idle += 1;
+
+        loop {
+            if queue.done {
+                return None;
+            } else if let Some(prefix) = queue.pending.pop_front() {
+                queue.idle -= 1;
+                return Some(prefix);
+            } else if queue.idle == self.jobs {
+                queue.done = true;
+                self.cond.notify_all();
+                return None;
+            }
+
+            queue = self.cond.wait(queue).unwrap();
+        }
+    }
+
+    // Whether there are idle workers waiting for work.
+    fn hungry(&self) -> bool {
+        let queue = self.queue.lock().unwrap();
+        queue.idle > 0 && queue.pending.is_empty()
+    }
+
+    fn donate(&self, prefix: Prefix) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.pending.push_back(prefix);
+        self.cond.notify_one();
+    }
+
+    // Terminate all workers, e.g. if one of them encountered an error.
+    fn abort(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.done = true;
+        self.cond.notify_all();
+    }
+}
+
+// Search strategy which hands pending paths over to idle workers
+// and otherwise defers to a worker-local search strategy.
+struct SharedSearch<'a, 'ctx, 'src> {
+    local: Box<dyn SearchStrategy<'ctx, 'src> + 'a>,
+    queue: &'a WorkQueue,
+}
+
+impl<'a, 'ctx, 'src> SearchStrategy<'ctx, 'src> for SharedSearch<'a, 'ctx, 'src> {
+    fn add(&mut self, mut paths: Vec<Path<'ctx, 'src>>) {
+        // Always keep at least one path to continue exploration locally.
+        while paths.len() > 1 && self.queue.hungry() {
+            let path = paths.pop().unwrap();
+            self.queue.donate(path.trace().to_vec());
+        }
+        self.local.add(paths)
+    }
+
+    fn next(&mut self, cov: &Coverage<'src>) -> Option<Path<'ctx, 'src>> {
+        self.local.next(cov)
+    }
+
+    fn pending(&self) -> Vec<&Path<'ctx, 'src>> {
+        self.local.pending()
+    }
+}
+
+pub fn exec_parallel(
+    jobs: usize,
+    source: &[Definition],
+    func: &str,
+    search: &str,
+    seed: u64,
+    opts: Options,
+) -> Result<(), Error> {
+    let queue = WorkQueue::new(jobs);
+    let run_worker = |id: usize| -> Result<(), Error> {
+        let mut cfg = Config::new();
+        cfg.set_model_generation(true);
+        let ctx = Context::new(&cfg);
+
+        let local = from_name(search, seed.wrapping_add(id as u64)).unwrap();
+        let mut strategy = SharedSearch {
+            local,
+            queue: &queue,
+        };
+
+        while let Some(prefix) = queue.take() {
+            let mut interp = Interp::new(&ctx, source, opts)?;
+            interp.exec_symbolic(func, vec![prefix], &mut strategy)?;
+        }
+
+        Ok(())
+    };
+
+    thread::scope(|s| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|id| {
+                let (queue, run_worker) = (&queue, &run_worker);
+                s.spawn(move || {
+                    let r = run_worker(id);
+                    if r.is_err() {
+                        queue.abort();
+                    }
+                    r
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .map(|w| w.join().unwrap())
+            .collect::<Result<(), Error>>()
+    })
+}
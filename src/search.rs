@@ -0,0 +1,182 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::interp::Path;
+
+// Identifies a block by function name and label.
+pub type BlockId<'src> = (&'src str, &'src str);
+
+// Set of blocks executed so far.
+pub type Coverage<'src> = HashSet<BlockId<'src>>;
+
+pub trait SearchStrategy<'ctx, 'src> {
+    // Add pending paths, ordered by preference of the interpreter.
+    fn add(&mut self, paths: Vec<Path<'ctx, 'src>>);
+
+    // Select the next path to explore, returns None if all
+    // pending paths have been explored.
+    fn next(&mut self, cov: &Coverage<'src>) -> Option<Path<'ctx, 'src>>;
+
+    // All pending paths, ordered such that the last path would be
+    // explored next if coverage doesn't change in the meantime.
+    fn pending(&self) -> Vec<&Path<'ctx, 'src>>;
+}
+
+pub fn from_name<'a, 'ctx: 'a, 'src: 'a>(
+    name: &str,
+    seed: u64,
+) -> Option<Box<dyn SearchStrategy<'ctx, 'src> + 'a>> {
+    match name {
+        "dfs" => Some(Box::new(DepthFirst::new())),
+        "bfs" => Some(Box::new(BreadthFirst::new())),
+        "random" => Some(Box::new(RandomSearch::new(seed))),
+        "coverage" => Some(Box::new(CoverageGuided::new())),
+        _ => None,
+    }
+}
+
+////
+// Depth-first search
+////
+
+#[derive(Default)]
+pub struct DepthFirst<'ctx, 'src> {
+    stack: Vec<Path<'ctx, 'src>>,
+}
+
+impl<'ctx, 'src> DepthFirst<'ctx, 'src> {
+    pub fn new() -> DepthFirst<'ctx, 'src> {
+        DepthFirst { stack: Vec::new() }
+    }
+}
+
+impl<'ctx, 'src> SearchStrategy<'ctx, 'src> for DepthFirst<'ctx, 'src> {
+    fn add(&mut self, paths: Vec<Path<'ctx, 'src>>) {
+        self.stack.extend(paths.into_iter().rev())
+    }
+
+    fn next(&mut self, _cov: &Coverage<'src>) -> Option<Path<'ctx, 'src>> {
+        self.stack.pop()
+    }
+
+    fn pending(&self) -> Vec<&Path<'ctx, 'src>> {
+        self.stack.iter().collect()
+    }
+}
+
+////
+// Breadth-first search
+////
+
+#[derive(Default)]
+pub struct BreadthFirst<'ctx, 'src> {
+    queue: VecDeque<Path<'ctx, 'src>>,
+}
+
+impl<'ctx, 'src> BreadthFirst<'ctx, 'src> {
+    pub fn new() -> BreadthFirst<'ctx, 'src> {
+        BreadthFirst {
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl<'ctx, 'src> SearchStrategy<'ctx, 'src> for BreadthFirst<'ctx, 'src> {
+    fn add(&mut self, paths: Vec<Path<'ctx, 'src>>) {
+        self.queue.extend(paths)
+    }
+
+    fn next(&mut self, _cov: &Coverage<'src>) -> Option<Path<'ctx, 'src>> {
+        self.queue.pop_front()
+    }
+
+    fn pending(&self) -> Vec<&Path<'ctx, 'src>> {
+        self.queue.iter().rev().collect()
+    }
+}
+
+////
+// Random search
+////
+
+pub struct RandomSearch<'ctx, 'src> {
+    paths: Vec<Path<'ctx, 'src>>,
+    rng: u64,
+}
+
+impl<'ctx, 'src> RandomSearch<'ctx, 'src> {
+    pub fn new(seed: u64) -> RandomSearch<'ctx, 'src> {
+        RandomSearch {
+            paths: Vec::new(),
+            // The xorshift state must never be zero.
+            rng: if seed == 0 { 0x2545f4914f6cdd1d } else { seed },
+        }
+    }
+
+    // xorshift64, see https://doi.org/10.18637/jss.v008.i14
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng = x;
+        x
+    }
+}
+
+impl<'ctx, 'src> SearchStrategy<'ctx, 'src> for RandomSearch<'ctx, 'src> {
+    fn add(&mut self, paths: Vec<Path<'ctx, 'src>>) {
+        self.paths.extend(paths)
+    }
+
+    fn next(&mut self, _cov: &Coverage<'src>) -> Option<Path<'ctx, 'src>> {
+        if self.paths.is_empty() {
+            return None;
+        }
+
+        let idx = self.next_u64() % self.paths.len() as u64;
+        Some(self.paths.swap_remove(idx as usize))
+    }
+
+    fn pending(&self) -> Vec<&Path<'ctx, 'src>> {
+        self.paths.iter().collect()
+    }
+}
+
+////
+// Coverage-guided search
+////
+
+// Prefers the most recently added path whose target block has not been
+// executed yet. If all targets are covered, this falls back to DFS.
+#[derive(Default)]
+pub struct CoverageGuided<'ctx, 'src> {
+    paths: Vec<Path<'ctx, 'src>>,
+}
+
+impl<'ctx, 'src> CoverageGuided<'ctx, 'src> {
+    pub fn new() -> CoverageGuided<'ctx, 'src> {
+        CoverageGuided { paths: Vec::new() }
+    }
+}
+
+impl<'ctx, 'src> SearchStrategy<'ctx, 'src> for CoverageGuided<'ctx, 'src> {
+    fn add(&mut self, paths: Vec<Path<'ctx, 'src>>) {
+        self.paths.extend(paths.into_iter().rev())
+    }
+
+    fn next(&mut self, cov: &Coverage<'src>) -> Option<Path<'ctx, 'src>> {
+        let uncovered = self
+            .paths
+            .iter()
+            .rposition(|p| !cov.contains(&p.block_id()));
+
+        match uncovered {
+            Some(idx) => Some(self.paths.remove(idx)),
+            None => self.paths.pop(),
+        }
+    }
+
+    fn pending(&self) -> Vec<&Path<'ctx, 'src>> {
+        self.paths.iter().collect()
+    }
+}
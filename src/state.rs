@@ -1,9 +1,11 @@
 use qbe_reader::types::*;
 use qbe_reader::Definition;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::rc::Rc;
 
 use z3::{
-    ast::{Ast, BV},
+    ast::{Ast, Bool, BV},
     Context,
 };
 
@@ -18,42 +20,111 @@ use crate::value::*;
 // TODO: Just store unconstrained symbolic bytes instead.
 const FUNC_PATTERN: u32 = 0xdeadbeef;
 
+// Size of the region starting at address zero which is never allocated.
+// Accesses to it, e.g. through null pointers, are detected as faults.
+pub const NULL_PAGE_SIZE: u64 = 0x1000;
+
+// Address of the memory region which the environment parameter of
+// the entry function points to. Memory is unconstrained symbolic
+// until written, hence the content of this region is symbolic too.
+// The address is chosen to be far away from the stack and data.
+pub const ENV_ADDR: u64 = 0x4000_0000_0000_0000;
+
+// Size of the environment region, accesses beyond it are out of bounds.
+const ENV_SIZE: u64 = 1 << 20;
+
+// Address of the heap, i.e. memory allocated using malloc(3).
+const HEAP_ADDR: u64 = 0x2000_0000_0000_0000;
+
+// Maximum size of a single heap allocation, larger allocations fail.
+// This ensures that heap objects never reach the TLS region.
+pub const MAX_ALLOC: u64 = 1 << 32;
+
+// Alignment of heap allocations, suitable for any type.
+const HEAP_ALIGN: u64 = 16;
+
+// Address of the memory region containing thread-local data. Only
+// a single thread is executed, hence only one such region exists.
+const TLS_ADDR: u64 = 0x3000_0000_0000_0000;
+
+fn align_up(n: u64, align: u64) -> u64 {
+    (n + align - 1) / align * align
+}
+
+#[derive(Clone)]
 struct FuncState<'ctx, 'src> {
+    func: &'src FuncDef,
     labels: HashMap<&'src str, &'src Block>,
     local: HashMap<&'src str, BV<'ctx>>,
 
+    // Conditions under which local variables depend on uninitialized
+    // memory. Variables without an entry are always initialized.
+    undef: HashMap<&'src str, Bool<'ctx>>,
+
     // Value of the stack pointer when this stack frame was created.
     stkptr: BV<'ctx>,
+
+    // Currently executed block and index of the next statement in it.
+    block: &'src Block,
+    pc: usize,
+
+    // Label of the previously executed block, used for PHI instructions.
+    prev_label: Option<&'src str>,
+
+    // Local variable of the caller which receives the return value,
+    // along with the return type specified by the call instruction.
+    ret_dest: Option<(&'src str, &'src Type)>,
+
+    // Additional arguments passed to a variadic function. These are
+    // presently only recorded as qbe-reader doesn't parse vastart and
+    // vaarg yet, which would be required to access them.
+    varargs: Vec<BV<'ctx>>,
 }
 
+// The state is cloned on every conditional jump. Z3 terms are reference
+// counted and immutable, hence cloning the memory is cheap. Stack frames
+// are shared between clones and only copied when modified (copy-on-write).
+#[derive(Clone)]
 pub struct State<'ctx, 'src> {
     v: ValueFactory<'ctx>,
     pub mem: Memory<'ctx>,
     stkptr: BV<'ctx>,
+    heapptr: BV<'ctx>,
 
-    func: HashMap<&'src str, (BV<'ctx>, &'src FuncDef)>,
-    data: HashMap<&'src str, (BV<'ctx>, &'src DataDef)>,
-    stck: Vec<FuncState<'ctx, 'src>>,
+    func: Rc<HashMap<&'src str, (BV<'ctx>, &'src FuncDef)>>,
+    data: Rc<HashMap<&'src str, (BV<'ctx>, &'src DataDef)>>,
+    tls: Rc<HashMap<&'src str, (BV<'ctx>, &'src DataDef)>>,
+    types: Rc<HashMap<&'src str, &'src TypeDef>>,
+    stck: Vec<Rc<FuncState<'ctx, 'src>>>,
 }
 
 impl<'ctx, 'src> State<'ctx, 'src> {
-    pub fn new(
-        ctx: &'ctx Context,
-        source: &'src Vec<Definition>,
-    ) -> Result<State<'ctx, 'src>, Error> {
+    pub fn new(ctx: &'ctx Context, source: &'src [Definition]) -> Result<State<'ctx, 'src>, Error> {
         let v = ValueFactory::new(ctx);
         let mut state = State {
             stkptr: v.make_long(0),
+            heapptr: v.make_long(HEAP_ADDR),
             v,
 
-            func: HashMap::new(),
-            data: HashMap::new(),
+            func: Rc::new(HashMap::new()),
+            data: Rc::new(HashMap::new()),
+            tls: Rc::new(HashMap::new()),
+            types: Rc::new(HashMap::new()),
             stck: Vec::new(),
 
             mem: Memory::new(ctx),
         };
 
-        let mut func_end_ptr = state.v.make_long(0);
+        let types = source.iter().filter_map(|x| match x {
+            Definition::Type(t) => Some((t.name.as_str(), t)),
+            _ => None,
+        });
+        state.types = Rc::new(HashMap::from_iter(types));
+
+        let (env, env_size) = (state.v.make_long(ENV_ADDR), state.v.make_long(ENV_SIZE));
+        state.mem.add_object(env, env_size, ObjectKind::Env);
+
+        let mut func_end_ptr = state.v.make_long(NULL_PAGE_SIZE);
         for x in source.into_iter() {
             if let Definition::Func(f) = x {
                 func_end_ptr = state.add_func(func_end_ptr.clone(), f);
@@ -61,9 +132,16 @@ impl<'ctx, 'src> State<'ctx, 'src> {
         }
 
         let mut data_end_ptr = func_end_ptr.clone();
+        let mut tls_end_ptr = state.v.make_long(TLS_ADDR);
         for x in source.into_iter() {
-            if let Definition::Data(d) = x {
-                data_end_ptr = state.add_data(data_end_ptr.clone(), d)?;
+            match x {
+                Definition::Data(d) if d.linkage.thread => {
+                    tls_end_ptr = state.add_data(tls_end_ptr.clone(), d)?;
+                }
+                Definition::Data(d) => {
+                    data_end_ptr = state.add_data(data_end_ptr.clone(), d)?;
+                }
+                _ => {}
             }
         }
 
@@ -76,19 +154,27 @@ impl<'ctx, 'src> State<'ctx, 'src> {
             .store_word(addr.clone(), self.v.make_word(FUNC_PATTERN));
         let end_addr = addr.bvadd(&self.v.make_long(4));
 
-        self.func.insert(&func.name, (addr.clone(), func));
+        Rc::make_mut(&mut self.func).insert(&func.name, (addr.clone(), func));
         end_addr
     }
 
     fn add_data(&mut self, addr: BV<'ctx>, data: &'src DataDef) -> Result<BV<'ctx>, Error> {
         // Insert into map before actually inserting the data into memory
         // to support self-referencing data decls: `data $c = { l $c }`.
-        self.data.insert(&data.name, (addr.clone(), data));
+        let map = if data.linkage.thread {
+            &mut self.tls
+        } else {
+            &mut self.data
+        };
+        Rc::make_mut(map).insert(&data.name, (addr.clone(), data));
 
-        let mut end_addr = addr;
+        let mut end_addr = addr.clone();
         for obj in data.objs.iter() {
             end_addr = self.insert_data_object(end_addr.clone(), obj)?;
         }
+
+        let size = end_addr.bvsub(&addr);
+        self.mem.add_object(addr, size, ObjectKind::Data);
         Ok(end_addr)
     }
 
@@ -102,9 +188,9 @@ impl<'ctx, 'src> State<'ctx, 'src> {
             }
             DataObj::ZeroFill(n) => {
                 let zero = self.v.make_byte(0);
-                for i in 0..*n {
-                    cur_addr = cur_addr.bvadd(&self.v.make_long(i));
-                    self.mem.store_byte(cur_addr.clone(), zero.clone())
+                for _ in 0..*n {
+                    self.mem.store_byte(cur_addr.clone(), zero.clone());
+                    cur_addr = cur_addr.bvadd(&self.v.make_long(1));
                 }
             }
         }
@@ -152,11 +238,15 @@ impl<'ctx, 'src> State<'ctx, 'src> {
                     assert!(size % 8 == 0);
                     cur_addr = cur_addr.bvadd(&self.v.make_long(size / 8));
                 }
-                Const::SFP(_) => {
-                    panic!("single precision floating points not supported")
+                Const::SFP(f) => {
+                    self.mem
+                        .store_bitvector(cur_addr.clone(), self.v.from_f32(*f));
+                    cur_addr = cur_addr.bvadd(&self.v.make_long(4));
                 }
-                Const::DFP(_) => {
-                    panic!("double precision floating points not supported")
+                Const::DFP(f) => {
+                    self.mem
+                        .store_bitvector(cur_addr.clone(), self.v.from_f64(*f));
+                    cur_addr = cur_addr.bvadd(&self.v.make_long(8));
                 }
                 Const::Global(_) => unreachable!(),
             },
@@ -176,6 +266,18 @@ impl<'ctx, 'src> State<'ctx, 'src> {
         }
     }
 
+    // Address of thread-local data, referenced using `thread $name`.
+    pub fn get_tls_ptr(&self, name: &str) -> Option<BV<'ctx>> {
+        self.tls.get(name).map(|(addr, _)| addr.clone())
+    }
+
+    // Addresses of all functions, ordered by function name.
+    pub fn get_funcs(&self) -> Vec<(BV<'ctx>, &'src FuncDef)> {
+        let mut funcs: Vec<_> = self.func.values().cloned().collect();
+        funcs.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+        funcs
+    }
+
     pub fn get_func(&mut self, name: &str) -> Option<&'src FuncDef> {
         Some(self.func.get(name)?.1)
     }
@@ -186,7 +288,6 @@ impl<'ctx, 'src> State<'ctx, 'src> {
 
     pub fn stack_alloc(&mut self, align: u8, size: u64) -> BV<'ctx> {
         let align = align as u64;
-        assert!(self.stck.len() != 0);
 
         // (addr - (addr % alignment)) + alignment
         let aligned_addr = self
@@ -196,22 +297,115 @@ impl<'ctx, 'src> State<'ctx, 'src> {
         self.stkptr = aligned_addr.bvadd(&self.v.make_long(size));
 
         assert!(aligned_addr.get_size() == LONG_SIZE);
-        aligned_addr.clone()
+        self.mem.add_object(
+            aligned_addr.clone(),
+            self.v.make_long(size),
+            ObjectKind::Stack,
+        );
+        aligned_addr
+    }
+
+    // Allocate memory of the given (possibly symbolic) size on the heap.
+    pub fn heap_alloc(&mut self, size: &BV<'ctx>) -> BV<'ctx> {
+        let addr = self.heapptr.clone();
+
+        // At least one byte is reserved, even for zero-sized objects,
+        // such that each heap object has a unique address.
+        let one = self.v.make_long(1);
+        let reserved = size._eq(&self.v.make_long(0)).ite(&one, size);
+
+        // (addr + size + (align - 1)) & ~(align - 1)
+        let end = addr
+            .bvadd(&reserved)
+            .bvadd(&self.v.make_long(HEAP_ALIGN - 1));
+        self.heapptr = end.bvand(&self.v.make_long(!(HEAP_ALIGN - 1)));
+
+        self.mem
+            .add_object(addr.clone(), size.clone(), ObjectKind::Heap);
+        addr
+    }
+
+    /////
+    // Aggregate types
+    /////
+
+    // Size and alignment (in bytes) of the aggregate type with the given name.
+    pub fn type_layout(&self, name: &str) -> Result<(u64, u64), Error> {
+        let def = self
+            .types
+            .get(name)
+            .ok_or(Error::UnknownType(name.to_string()))?;
+
+        let (size, align) = match &def.defn {
+            AggregateType::Regular(members) => self.members_layout(members)?,
+            AggregateType::Union(variants) => {
+                let mut layout = (0, 1);
+                for members in variants.iter() {
+                    let (size, align) = self.members_layout(members)?;
+                    layout = (layout.0.max(size), layout.1.max(align));
+                }
+                layout
+            }
+            AggregateType::Opaque(size) => (*size, 1),
+        };
+
+        // The size of an aggregate is a multiple of its alignment.
+        let align = def.align.unwrap_or(align);
+        Ok((align_up(size, align), align))
+    }
+
+    fn members_layout(&self, members: &[(SubType, u64)]) -> Result<(u64, u64), Error> {
+        let (mut size, mut align) = (0, 1);
+        for (ty, count) in members.iter() {
+            let (member_size, member_align) = match ty {
+                SubType::ExtType(ty) => {
+                    let n = (ValueFactory::extty_to_size(*ty) / 8) as u64;
+                    (n, n)
+                }
+                SubType::UserDef(name) => self.type_layout(name)?,
+            };
+
+            size = align_up(size, member_align) + member_size * count;
+            align = align.max(member_align);
+        }
+
+        Ok((size, align))
     }
 
     /////
     // Function-local operations
     /////
 
-    pub fn push_func(&mut self, func: &'src FuncDef) {
+    pub fn push_func(
+        &mut self,
+        func: &'src FuncDef,
+        entry: &'src Block,
+        ret_dest: Option<(&'src str, &'src Type)>,
+        varargs: Vec<BV<'ctx>>,
+    ) {
         let blocks = func.body.iter().map(|blk| (blk.label.as_str(), blk));
         let state = FuncState {
+            func,
             labels: HashMap::from_iter(blocks),
             local: HashMap::new(),
+            undef: HashMap::new(),
             stkptr: self.stkptr.clone(),
+            block: entry,
+            pc: 0,
+            prev_label: None,
+            ret_dest,
+            varargs,
         };
 
-        self.stck.push(state);
+        self.stck.push(Rc::new(state));
+    }
+
+    fn cur_func_mut(&mut self) -> &mut FuncState<'ctx, 'src> {
+        Rc::make_mut(self.stck.last_mut().unwrap())
+    }
+
+    pub fn get_cur_func(&self) -> &'src FuncDef {
+        self.stck.last().unwrap().func
     }
 
     pub fn get_block(&self, name: &str) -> Option<&'src Block> {
@@ -219,9 +413,65 @@ impl<'ctx, 'src> State<'ctx, 'src> {
         func.labels.get(name).map(|b| *b)
     }
 
+    pub fn get_cur_block(&self) -> &'src Block {
+        self.stck.last().unwrap().block
+    }
+
+    pub fn get_pc(&self) -> usize {
+        self.stck.last().unwrap().pc
+    }
+
+    pub fn set_block(&mut self, block: &'src Block) {
+        let func = self.cur_func_mut();
+        func.block = block;
+        func.pc = 0;
+    }
+
+    // Returns the next statement of the current block and advances
+    // the program counter. Returns None if all statements have been
+    // executed, i.e. if the jump instruction is next.
+    pub fn next_stat(&mut self) -> Option<&'src Statement> {
+        let func = self.cur_func_mut();
+        let block = func.block;
+        let stat = block.inst.get(func.pc)?;
+        func.pc += 1;
+        Some(stat)
+    }
+
+    // Steps back to the previously returned statement, which
+    // is therefore executed again when execution continues.
+    pub fn rewind_stat(&mut self) {
+        let func = self.cur_func_mut();
+        assert!(func.pc > 0);
+        func.pc -= 1;
+    }
+
+    pub fn get_prev_label(&self) -> Option<&'src str> {
+        self.stck.last().unwrap().prev_label
+    }
+
+    pub fn set_prev_label(&mut self, label: &'src str) {
+        self.cur_func_mut().prev_label = Some(label);
+    }
+
     pub fn add_local(&mut self, name: &'src str, value: BV<'ctx>) {
-        let func = self.stck.last_mut().unwrap();
+        let func = self.cur_func_mut();
         func.local.insert(name, value);
+        func.undef.remove(name);
+    }
+
+    // Records that the given local variable depends on uninitialized
+    // memory if the condition holds, must be called after add_local.
+    pub fn set_undef(&mut self, name: &'src str, cond: Bool<'ctx>) {
+        let cond = cond.simplify();
+        if cond.as_bool() != Some(false) {
+            self.cur_func_mut().undef.insert(name, cond);
+        }
+    }
+
+    pub fn get_undef(&self, name: &str) -> Option<Bool<'ctx>> {
+        let func = self.stck.last().unwrap();
+        func.undef.get(name).cloned()
     }
 
     pub fn get_local(&self, name: &str) -> Option<BV<'ctx>> {
@@ -231,20 +481,96 @@ impl<'ctx, 'src> State<'ctx, 'src> {
         func.local.get(name).cloned()
     }
 
-    pub fn pop_func(&mut self) {
+    // Condition under which the address points into the stack frame of
+    // the current function, i.e. to memory allocated by this function.
+    pub fn in_frame(&self, addr: &BV<'ctx>) -> Bool<'ctx> {
+        let func = self.stck.last().unwrap();
+        Bool::and(
+            addr.get_ctx(),
+            &[&addr.bvuge(&func.stkptr), &addr.bvult(&self.stkptr)],
+        )
+    }
+
+    // Pops the current stack frame and returns the name of the local
+    // variable in the caller's frame which receives the return value.
+    pub fn pop_func(&mut self) -> Option<(&'src str, &'src Type)> {
         let func = self.stck.pop().unwrap();
-        self.stkptr = func.stkptr;
+        self.mem.pop_stack(&func.stkptr);
+        self.stkptr = func.stkptr.clone();
+        func.ret_dest
+    }
+
+    /////
+    // State merging
+    /////
+
+    // Position and amount of variadic arguments of each stack frame,
+    // states with an equal merge key can be merged using State::merge.
+    pub fn merge_key(&self) -> Vec<(&'src str, &'src str, usize, usize)> {
+        self.stck
+            .iter()
+            .map(|f| {
+                let pos = (f.func.name.as_str(), f.block.label.as_str());
+                (pos.0, pos.1, f.pc, f.varargs.len())
+            })
+            .collect()
+    }
+
+    // Merge the given state into this state. Afterwards, values of this
+    // state are used if the guard holds and values of the other state
+    // otherwise. Both states must have the same merge key.
+    pub fn merge(&mut self, other: &State<'ctx, 'src>, guard: &Bool<'ctx>) {
+        assert!(self.merge_key() == other.merge_key());
+
+        self.mem.merge(&other.mem, guard);
+        self.stkptr = guard.ite(&self.stkptr, &other.stkptr);
+        self.heapptr = guard.ite(&self.heapptr, &other.heapptr);
+
+        for (frame, other_frame) in self.stck.iter_mut().zip(other.stck.iter()) {
+            let frame = Rc::make_mut(frame);
+            frame.stkptr = guard.ite(&frame.stkptr, &other_frame.stkptr);
+            for (arg, other_arg) in frame.varargs.iter_mut().zip(other_frame.varargs.iter()) {
+                *arg = guard.ite(arg, other_arg);
+            }
+
+            for (name, value) in other_frame.local.iter() {
+                let merged = match frame.local.get(name) {
+                    Some(v) if v == value => continue,
+                    Some(v) => guard.ite(v, value),
+                    // XXX: Only defined on the other path, hence
+                    // it shouldn't be used after the join point.
+                    None => value.clone(),
+                };
+                frame.local.insert(*name, merged);
+            }
+
+            let no = Bool::from_bool(guard.get_ctx(), false);
+            let names: HashSet<_> = frame
+                .undef
+                .keys()
+                .chain(other_frame.undef.keys())
+                .copied()
+                .collect();
+            for name in names {
+                let cond = frame.undef.get(name).unwrap_or(&no);
+                let other_cond = other_frame.undef.get(name).unwrap_or(&no);
+                if cond != other_cond {
+                    let merged = guard.ite(cond, other_cond);
+                    frame.undef.insert(name, merged);
+                }
+            }
+        }
     }
 
     // TODO: Remove this
-    pub fn dump_locals(&self) {
+    pub fn dump_locals(&self, out: &mut String) {
         let func = self.stck.last().unwrap();
 
         let mut v: Vec<_> = func.local.iter().collect();
         v.sort_by_key(|a| a.0);
 
         for (key, value) in v.iter() {
-            println!("\t{} = {}", key, value.simplify());
+            writeln!(out, "\t{} = {}", key, value.simplify()).unwrap();
         }
     }
 }
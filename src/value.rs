@@ -1,5 +1,9 @@
 use qbe_reader::types::*;
-use z3::{ast::BV, Context};
+use z3::{
+    ast::{Ast, Bool, Dynamic, Float, BV},
+    Context,
+};
+use z3_sys::*;
 
 // TODO: Would be cool if we could enforce some additional type
 // safety via this abstraction. For example, avoiding that BVs
@@ -10,6 +14,7 @@ pub const HALF_SIZE: u32 = 16;
 pub const WORD_SIZE: u32 = 32;
 pub const LONG_SIZE: u32 = 64;
 
+#[derive(Clone)]
 pub struct ValueFactory<'ctx> {
     ctx: &'ctx Context,
 }
@@ -27,12 +32,16 @@ impl<'ctx> ValueFactory<'ctx> {
         match ty {
             BaseType::Word => WORD_SIZE,
             BaseType::Long => LONG_SIZE,
-            BaseType::Single => panic!("floating points not supported"),
-            BaseType::Double => panic!("floating points not supported"),
+            BaseType::Single => WORD_SIZE,
+            BaseType::Double => LONG_SIZE,
         }
     }
 
-    fn extty_to_size(ty: ExtType) -> u32 {
+    pub fn is_float(ty: BaseType) -> bool {
+        ty == BaseType::Single || ty == BaseType::Double
+    }
+
+    pub fn extty_to_size(ty: ExtType) -> u32 {
         match ty {
             ExtType::Base(b) => Self::basety_to_size(b),
             ExtType::Byte => BYTE_SIZE,
@@ -159,6 +168,103 @@ impl<'ctx> ValueFactory<'ctx> {
         val.zero_ext(tgt_size - cur_size)
     }
 
+    ////
+    // Floating Point Operations
+    ////
+
+    // Floating point values are represented as bitvectors holding their
+    // IEEE-754 bit pattern. Thereby, they can be stored in memory and
+    // merged like any other value. For arithmetic and comparisons, the
+    // bitvectors are converted to Z3 floating-point terms. The rounding
+    // mode is always round to nearest, ties to even.
+
+    pub fn from_f32(&self, v: f32) -> BV<'ctx> {
+        BV::from_u64(self.ctx, v.to_bits().into(), WORD_SIZE)
+    }
+
+    pub fn from_f64(&self, v: f64) -> BV<'ctx> {
+        BV::from_u64(self.ctx, v.to_bits(), LONG_SIZE)
+    }
+
+    fn to_float(&self, val: &BV<'ctx>) -> Float<'ctx> {
+        let ctx = self.ctx.get_z3_context();
+        unsafe {
+            let sort = match val.get_size() {
+                WORD_SIZE => Z3_mk_fpa_sort_single(ctx),
+                LONG_SIZE => Z3_mk_fpa_sort_double(ctx),
+                n => panic!("invalid floating point size: {}", n),
+            };
+            Float::wrap(self.ctx, Z3_mk_fpa_to_fp_bv(ctx, val.get_z3_ast(), sort))
+        }
+    }
+
+    fn from_float(&self, val: &Float<'ctx>) -> BV<'ctx> {
+        let ctx = self.ctx.get_z3_context();
+        unsafe { BV::wrap(self.ctx, Z3_mk_fpa_to_ieee_bv(ctx, val.get_z3_ast())) }
+    }
+
+    fn float_arith(
+        &self,
+        op: unsafe extern "C" fn(Z3_context, Z3_ast, Z3_ast, Z3_ast) -> Z3_ast,
+        val1: &BV<'ctx>,
+        val2: &BV<'ctx>,
+    ) -> BV<'ctx> {
+        let ctx = self.ctx.get_z3_context();
+        let (f1, f2) = (self.to_float(val1), self.to_float(val2));
+        let r = unsafe {
+            let rm = Dynamic::wrap(self.ctx, Z3_mk_fpa_rne(ctx));
+            let ast = op(ctx, rm.get_z3_ast(), f1.get_z3_ast(), f2.get_z3_ast());
+            Float::wrap(self.ctx, ast)
+        };
+        self.from_float(&r)
+    }
+
+    fn float_cmp(
+        &self,
+        op: unsafe extern "C" fn(Z3_context, Z3_ast, Z3_ast) -> Z3_ast,
+        val1: &BV<'ctx>,
+        val2: &BV<'ctx>,
+    ) -> Bool<'ctx> {
+        let ctx = self.ctx.get_z3_context();
+        let (f1, f2) = (self.to_float(val1), self.to_float(val2));
+        unsafe { Bool::wrap(self.ctx, op(ctx, f1.get_z3_ast(), f2.get_z3_ast())) }
+    }
+
+    pub fn float_add(&self, val1: &BV<'ctx>, val2: &BV<'ctx>) -> BV<'ctx> {
+        self.float_arith(Z3_mk_fpa_add, val1, val2)
+    }
+    pub fn float_sub(&self, val1: &BV<'ctx>, val2: &BV<'ctx>) -> BV<'ctx> {
+        self.float_arith(Z3_mk_fpa_sub, val1, val2)
+    }
+    pub fn float_mul(&self, val1: &BV<'ctx>, val2: &BV<'ctx>) -> BV<'ctx> {
+        self.float_arith(Z3_mk_fpa_mul, val1, val2)
+    }
+
+    pub fn float_neg(&self, val: &BV<'ctx>) -> BV<'ctx> {
+        let ctx = self.ctx.get_z3_context();
+        let f = self.to_float(val);
+        let r = unsafe { Float::wrap(self.ctx, Z3_mk_fpa_neg(ctx, f.get_z3_ast())) };
+        self.from_float(&r)
+    }
+
+    // Comparisons are false if either operand is NaN, except
+    // for the inequality comparison which is true in this case.
+    pub fn float_eq(&self, val1: &BV<'ctx>, val2: &BV<'ctx>) -> Bool<'ctx> {
+        self.float_cmp(Z3_mk_fpa_eq, val1, val2)
+    }
+    pub fn float_lt(&self, val1: &BV<'ctx>, val2: &BV<'ctx>) -> Bool<'ctx> {
+        self.float_cmp(Z3_mk_fpa_lt, val1, val2)
+    }
+    pub fn float_le(&self, val1: &BV<'ctx>, val2: &BV<'ctx>) -> Bool<'ctx> {
+        self.float_cmp(Z3_mk_fpa_leq, val1, val2)
+    }
+    pub fn float_gt(&self, val1: &BV<'ctx>, val2: &BV<'ctx>) -> Bool<'ctx> {
+        self.float_cmp(Z3_mk_fpa_gt, val1, val2)
+    }
+    pub fn float_ge(&self, val1: &BV<'ctx>, val2: &BV<'ctx>) -> Bool<'ctx> {
+        self.float_cmp(Z3_mk_fpa_geq, val1, val2)
+    }
+
     ////
     // Syntatic Sugar
     ////